@@ -0,0 +1,102 @@
+use crate::geo::{Vec3, Vec3f};
+
+/// Crystal-like anisotropy that makes particles stickier along the preferred
+/// directions of a lattice, growing snowflake and dendrite like patterns.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Anisotropy {
+    pub symmetry: Symmetry,
+
+    /// How much the attachment direction matters, between 0 and 1. With 0 the
+    /// growth is isotropic while with 1 particles never stick along the least
+    /// favored directions.
+    pub strength: f64,
+
+    /// How many times a walker must hit an empty site before the site is
+    /// occupied, walkers hitting the site in the meantime are respawned. This
+    /// reduces the noise in the growth and makes the symmetry more visible.
+    pub hits: u32,
+}
+
+/// The symmetry of the preferred attachment directions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Symmetry {
+    /// Growth is favored along the x, y and z axes.
+    Cubic,
+
+    /// Growth is favored along six directions 60° apart in the xy plane and
+    /// along the z axis.
+    Hexagonal,
+}
+
+impl Anisotropy {
+    pub fn new(symmetry: Symmetry, strength: f64) -> Self {
+        Anisotropy {
+            symmetry,
+            strength,
+            hits: 1,
+        }
+    }
+
+    /// Probability, between 0 and 1, that a particle attaching from direction
+    /// `d`, relative to the cell it's sticking to, actually sticks.
+    pub fn stickiness(&self, d: Vec3) -> f64 {
+        if d == Vec3::new(0, 0, 0) {
+            return 1.0;
+        }
+
+        let u = Vec3f::from(d).normalized().abs();
+
+        let alignment = match self.symmetry {
+            Symmetry::Cubic => {
+                // 1 along the axes, 0 along the diagonals of the cube
+                let min = 1.0 / 3.0_f64.sqrt();
+                (u.x.max(u.y).max(u.z) - min) / (1.0 - min)
+            }
+            Symmetry::Hexagonal => {
+                let theta = u.y.atan2(u.x);
+                let planar = (1.0 + (6.0 * theta).cos()) / 2.0;
+                let horizontal = 1.0 - u.z;
+
+                // the z axis is just as preferred as the in plane directions
+                (planar * horizontal).max(u.z.powi(2) * 2.0 - 1.0)
+            }
+        };
+
+        1.0 - self.strength * (1.0 - alignment.clamp(0.0, 1.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::{growth::Connectivity, Dla, Event, GrowthModel};
+
+    #[test]
+    fn cubic_anisotropy_never_grows_along_the_diagonals() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.set_growth_model(GrowthModel::Lattice(Connectivity::TwentySix));
+        dla.set_anisotropy(Some(Anisotropy {
+            hits: 3,
+            ..Anisotropy::new(Symmetry::Cubic, 1.0)
+        }));
+
+        let mut respawns = 0;
+        for _ in 0..100 {
+            dla.add_with(&mut rng, &mut |e: &Event| match e {
+                Event::Attached { cell, parent, .. } => assert!(cell.dist2(*parent) < 3),
+                Event::Respawned { .. } => respawns += 1,
+                _ => {}
+            })
+            .unwrap();
+        }
+
+        assert_eq!(dla.len(), 101);
+        assert!(respawns > 0);
+    }
+}
@@ -0,0 +1,782 @@
+use std::fmt;
+
+use crate::anisotropy::Anisotropy;
+use crate::geo::{Vec3, MAX_COORD};
+use crate::shape::Shape;
+use crate::species::{Species, SpeciesId, MAX_SPECIES};
+use crate::{Attractor, Dla, Domain, GrowthModel};
+
+/// The smallest attraction radius the diffusion and ballistic models support,
+/// walkers move by a random multiple of half of it at each step.
+pub const MIN_ATTRACTION_RADIUS: u16 = 4;
+
+/// Configuration of a `Dla` that's validated all at once when the `Dla` is
+/// built.
+#[derive(Debug, Clone)]
+pub struct DlaBuilder {
+    spawn_radius: u32,
+    attraction_radius: u16,
+    seeds: Vec<Vec3>,
+    obstacles: Vec<Shape>,
+    containers: Vec<Shape>,
+    attractors: Vec<Attractor>,
+    sinks: Vec<Shape>,
+    stickiness: f64,
+    anisotropy: Option<Anisotropy>,
+    species: Vec<Species>,
+    domain: Domain,
+    growth_model: GrowthModel,
+    collect_stats: bool,
+}
+
+/// The reasons why a `DlaBuilder` can fail to build a `Dla`, or why a `Dla`
+/// can fail to keep growing.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DlaError {
+    /// There are no seeds to start growing the DLA from.
+    NoSeeds,
+
+    /// The attraction radius, of the DLA or of one of its species, is too
+    /// small for the growth model.
+    AttractionRadiusTooSmall { radius: u16, min: u16 },
+
+    /// A seed, or a corner of the periodic domain, is beyond `MAX_COORD` and
+    /// distances would overflow.
+    CoordinateOverflow(Vec3),
+
+    /// A DLA was tiled zero times along an axis, leaving no copy at all.
+    EmptyTiling([u32; 3]),
+
+    /// A stickiness is not a probability between 0 and 1.
+    InvalidStickiness(f64),
+
+    /// A size of a shape, like the radius of a sphere, is negative.
+    NegativeShapeSize { size: &'static str, value: i64 },
+
+    /// A spawn ratio is negative or not finite.
+    InvalidSpawnRatio(f64),
+
+    /// The spawn ratios of all the species are 0, no walker could ever be
+    /// spawned.
+    NoSpawnRatio,
+
+    /// A species id is not below `MAX_SPECIES`.
+    SpeciesOutOfRange(SpeciesId),
+
+    /// A cell belongs to a species the DLA doesn't have.
+    UnknownSpecies { cell: Vec3, species: SpeciesId },
+
+    /// Walkers would never stick to the DLA because its stickiness is 0, or
+    /// because the walkers of the given species have a stickiness of 0 to
+    /// every species that can grow.
+    NeverSticks(Option<SpeciesId>),
+
+    /// The Eden growth model doesn't support the given option.
+    UnsupportedByEden(&'static str),
+
+    /// The strength of the anisotropy is not between 0 and 1.
+    InvalidAnisotropyStrength(f64),
+
+    /// The pull of an attractor is not a probability between 0 and 1.
+    InvalidAttractorPull(f64),
+
+    /// An attractor pulls the walkers but the growth model never makes them
+    /// drift, only diffusing walkers can be pulled.
+    AttractorPullUnsupported(GrowthModel),
+
+    /// The range of an attractor is negative or not finite.
+    InvalidAttractorRange(f64),
+
+    /// A seed is inside an obstacle or outside a container.
+    SeedNotFree(Vec3),
+
+    /// No free cell where to spawn walkers could be found, the spawn area is
+    /// covered by obstacles and sinks or outside the containers.
+    NoFreeSpawnCell,
+
+    /// The Eden model cannot grow anymore since no site next to the DLA is
+    /// free.
+    NoFreePerimeterSite,
+}
+
+impl DlaBuilder {
+    /// A builder with the same defaults as the CLI, growing a diffusion
+    /// limited aggregate around the origin.
+    pub fn new() -> Self {
+        DlaBuilder {
+            spawn_radius: 10,
+            attraction_radius: 8,
+            seeds: vec![Vec3::new(0, 0, 0)],
+            obstacles: vec![],
+            containers: vec![],
+            attractors: vec![],
+            sinks: vec![],
+            stickiness: 1.0,
+            anisotropy: None,
+            species: vec![],
+            domain: Domain::Unbounded,
+            growth_model: GrowthModel::Diffusion,
+            collect_stats: false,
+        }
+    }
+
+    pub fn spawn_radius(mut self, spawn_radius: u32) -> Self {
+        self.spawn_radius = spawn_radius;
+        self
+    }
+
+    pub fn attraction_radius(mut self, attraction_radius: u16) -> Self {
+        self.attraction_radius = attraction_radius;
+        self
+    }
+
+    /// Replace the seeds the DLA grows from.
+    pub fn seeds(mut self, seeds: impl IntoIterator<Item = Vec3>) -> Self {
+        self.seeds = seeds.into_iter().collect();
+        self
+    }
+
+    pub fn obstacle(mut self, obstacle: Shape) -> Self {
+        self.obstacles.push(obstacle);
+        self
+    }
+
+    pub fn container(mut self, container: Shape) -> Self {
+        self.containers.push(container);
+        self
+    }
+
+    pub fn attractor(mut self, attractor: Attractor) -> Self {
+        self.attractors.push(attractor);
+        self
+    }
+
+    pub fn sink(mut self, sink: Shape) -> Self {
+        self.sinks.push(sink);
+        self
+    }
+
+    pub fn stickiness(mut self, stickiness: f64) -> Self {
+        self.stickiness = stickiness;
+        self
+    }
+
+    pub fn anisotropy(mut self, anisotropy: Option<Anisotropy>) -> Self {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    pub fn species(mut self, species: Vec<Species>) -> Self {
+        self.species = species;
+        self
+    }
+
+    pub fn domain(mut self, domain: Domain) -> Self {
+        self.domain = domain;
+        self
+    }
+
+    pub fn growth_model(mut self, growth_model: GrowthModel) -> Self {
+        self.growth_model = growth_model;
+        self
+    }
+
+    pub fn collect_stats(mut self, enabled: bool) -> Self {
+        self.collect_stats = enabled;
+        self
+    }
+
+    /// Validate the configuration and build the `Dla`.
+    pub fn build(self) -> Result<Dla, DlaError> {
+        self.validate()?;
+
+        let mut dla = Dla::new(self.spawn_radius, self.attraction_radius, self.seeds)
+            .ok_or(DlaError::NoSeeds)?;
+
+        dla.set_growth_model(self.growth_model);
+        dla.set_domain(self.domain);
+        dla.set_stickiness(self.stickiness);
+        dla.set_anisotropy(self.anisotropy);
+        dla.set_species(self.species);
+        dla.collect_stats(self.collect_stats);
+        for o in self.obstacles {
+            dla.add_obstacle(o);
+        }
+        for c in self.containers {
+            dla.add_container(c);
+        }
+        for a in self.attractors {
+            dla.add_attractor(a);
+        }
+        for s in self.sinks {
+            dla.add_sink(s);
+        }
+        dla.check_free_space()?;
+
+        Ok(dla)
+    }
+
+    fn validate(&self) -> Result<(), DlaError> {
+        if self.seeds.is_empty() {
+            return Err(DlaError::NoSeeds);
+        }
+        if self.species.len() > MAX_SPECIES {
+            return Err(DlaError::SpeciesOutOfRange(self.species.len() - 1));
+        }
+
+        // the lattice and Eden models never look at the attraction radius to
+        // move the walkers
+        if matches!(
+            self.growth_model,
+            GrowthModel::Diffusion | GrowthModel::Ballistic
+        ) {
+            let radii = std::iter::once(self.attraction_radius)
+                .chain(self.species.iter().map(|s| s.attraction_radius));
+            for radius in radii {
+                if radius < MIN_ATTRACTION_RADIUS {
+                    return Err(DlaError::AttractionRadiusTooSmall {
+                        radius,
+                        min: MIN_ATTRACTION_RADIUS,
+                    });
+                }
+            }
+        }
+
+        // Eden growth occupies perimeter sites without any walk, there's
+        // nothing that could stick with a probability or be pulled around
+        if self.growth_model == GrowthModel::Eden {
+            let unsupported = [
+                ("species", self.species.len() > 1),
+                ("anisotropy", self.anisotropy.is_some()),
+                ("a stickiness other than 1", self.stickiness != 1.0),
+                ("attractors", !self.attractors.is_empty()),
+                ("sinks", !self.sinks.is_empty()),
+            ];
+            if let Some((option, _)) = unsupported.iter().find(|(_, given)| *given) {
+                return Err(DlaError::UnsupportedByEden(option));
+            }
+        }
+
+        let probabilities = std::iter::once(self.stickiness)
+            .chain(self.attractors.iter().map(|a| a.stickiness))
+            .chain(
+                self.species
+                    .iter()
+                    .flat_map(|s| s.stickiness.iter().copied()),
+            );
+        for p in probabilities {
+            if !(0.0..=1.0).contains(&p) {
+                return Err(DlaError::InvalidStickiness(p));
+            }
+        }
+
+        for s in &self.species {
+            if !(s.spawn_ratio >= 0.0 && s.spawn_ratio.is_finite()) {
+                return Err(DlaError::InvalidSpawnRatio(s.spawn_ratio));
+            }
+        }
+        let total = self.species.iter().map(|s| s.spawn_ratio).sum::<f64>();
+        if !self.species.is_empty() && total == 0.0 {
+            return Err(DlaError::NoSpawnRatio);
+        }
+        if !total.is_finite() {
+            return Err(DlaError::InvalidSpawnRatio(total));
+        }
+
+        // walkers that can't stick anywhere would wander forever
+        if self.stickiness == 0.0 {
+            return Err(DlaError::NeverSticks(None));
+        }
+        // the seeds belong to the first species, the other species can only
+        // grow once their walkers can stick to a species that already grew
+        let sticks = |s: &Species, grown: &[bool]| {
+            (0..grown.len()).any(|t| grown[t] && s.stickiness_to(t) > 0.0)
+        };
+        let mut grown = vec![false; self.species.len().max(1)];
+        grown[0] = true;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (i, s) in self.species.iter().enumerate() {
+                if !grown[i] && sticks(s, &grown) {
+                    grown[i] = true;
+                    changed = true;
+                }
+            }
+        }
+        for (i, s) in self.species.iter().enumerate() {
+            if s.spawn_ratio > 0.0 && !sticks(s, &grown) {
+                return Err(DlaError::NeverSticks(Some(i)));
+            }
+        }
+
+        if let Some(a) = &self.anisotropy {
+            if !(0.0..=1.0).contains(&a.strength) {
+                return Err(DlaError::InvalidAnisotropyStrength(a.strength));
+            }
+        }
+
+        let shapes = self
+            .obstacles
+            .iter()
+            .chain(&self.containers)
+            .chain(&self.sinks)
+            .chain(self.attractors.iter().map(|a| &a.shape));
+        for s in shapes {
+            s.validate()?;
+        }
+
+        for a in &self.attractors {
+            if !(0.0..=1.0).contains(&a.pull) {
+                return Err(DlaError::InvalidAttractorPull(a.pull));
+            }
+            // ballistic and lattice walkers still use the attractor
+            // stickiness, but their moves ignore the drift
+            if a.pull > 0.0
+                && matches!(
+                    self.growth_model,
+                    GrowthModel::Ballistic | GrowthModel::Lattice(_)
+                )
+            {
+                return Err(DlaError::AttractorPullUnsupported(self.growth_model));
+            }
+            if !(a.range >= 0.0 && a.range.is_finite()) {
+                return Err(DlaError::InvalidAttractorRange(a.range));
+            }
+        }
+
+        // the area walkers are spawned in is clamped to `MAX_COORD`, but the
+        // seeds and the domain must be within it in the first place
+        let mut out_of_bounds = self.seeds.iter().copied().chain(match &self.domain {
+            Domain::Unbounded => vec![],
+            Domain::Periodic(bbox) => vec![bbox.lower(), bbox.upper()],
+        });
+        if let Some(p) = out_of_bounds.find(|p| !p.is_bounded()) {
+            return Err(DlaError::CoordinateOverflow(p));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for DlaBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for DlaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DlaError::NoSeeds => write!(f, "the DLA needs at least one seed"),
+            DlaError::AttractionRadiusTooSmall { radius, min } => write!(
+                f,
+                "attraction radius {} is too small, it must be at least {}",
+                radius, min
+            ),
+            DlaError::CoordinateOverflow(p) => write!(
+                f,
+                "point ({},{},{}) is too far from the origin, coordinates must stay within ±{}",
+                p.x, p.y, p.z, MAX_COORD
+            ),
+            DlaError::EmptyTiling([x, y, z]) => write!(
+                f,
+                "cannot tile a DLA {}x{}x{} times, every count must be at least 1",
+                x, y, z
+            ),
+            DlaError::InvalidStickiness(s) => {
+                write!(f, "stickiness {} is not between 0 and 1", s)
+            }
+            DlaError::NegativeShapeSize { size, value } => {
+                write!(f, "the {} of a shape cannot be negative, got {}", size, value)
+            }
+            DlaError::InvalidSpawnRatio(r) => {
+                write!(f, "spawn ratio {} is not a finite non negative number", r)
+            }
+            DlaError::NoSpawnRatio => {
+                write!(f, "the spawn ratios of all the species are 0, no walker would spawn")
+            }
+            DlaError::SpeciesOutOfRange(s) => write!(
+                f,
+                "species {} is out of range, there can be at most {} species",
+                s, MAX_SPECIES
+            ),
+            DlaError::UnknownSpecies { cell, species } => write!(
+                f,
+                "cell ({},{},{}) belongs to unknown species {}",
+                cell.x, cell.y, cell.z, species
+            ),
+            DlaError::NeverSticks(None) => {
+                write!(f, "the stickiness is 0, walkers would never stick")
+            }
+            DlaError::NeverSticks(Some(s)) => write!(
+                f,
+                "walkers of species {} have a stickiness of 0 to every species they could meet and would never stick",
+                s
+            ),
+            DlaError::UnsupportedByEden(option) => {
+                write!(f, "the Eden growth model doesn't support {}", option)
+            }
+            DlaError::InvalidAnisotropyStrength(s) => {
+                write!(f, "anisotropy strength {} is not between 0 and 1", s)
+            }
+            DlaError::InvalidAttractorPull(p) => {
+                write!(f, "attractor pull {} is not between 0 and 1", p)
+            }
+            DlaError::AttractorPullUnsupported(model) => write!(
+                f,
+                "attractors cannot pull the walkers of the {:?} growth model, their pull must be 0",
+                model
+            ),
+            DlaError::InvalidAttractorRange(r) => {
+                write!(f, "attractor range {} is not a non negative number", r)
+            }
+            DlaError::SeedNotFree(p) => write!(
+                f,
+                "seed ({},{},{}) is inside an obstacle or outside a container",
+                p.x, p.y, p.z
+            ),
+            DlaError::NoFreeSpawnCell => write!(
+                f,
+                "cannot find a free cell where to spawn walkers, the spawn area is covered by obstacles and sinks or outside the containers"
+            ),
+            DlaError::NoFreePerimeterSite => {
+                write!(f, "no free site is left next to the DLA to grow into")
+            }
+        }
+    }
+}
+
+impl std::error::Error for DlaError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::anisotropy::Symmetry;
+    use crate::geo::Bbox;
+    use crate::growth::Connectivity;
+
+    #[test]
+    fn seeds_are_required() {
+        assert_eq!(
+            DlaBuilder::new().seeds(vec![]).build().unwrap_err(),
+            DlaError::NoSeeds
+        );
+    }
+
+    #[test]
+    fn seeds_must_be_within_max_coord() {
+        let far = Vec3::new(0, MAX_COORD + 1, 0);
+        assert_eq!(
+            DlaBuilder::new().seeds(vec![far]).build().unwrap_err(),
+            DlaError::CoordinateOverflow(far)
+        );
+        let min = Vec3::new(i64::MIN, 0, 0);
+        assert_eq!(
+            DlaBuilder::new().seeds(vec![min]).build().unwrap_err(),
+            DlaError::CoordinateOverflow(min)
+        );
+    }
+
+    #[test]
+    fn attraction_radius_must_be_large_enough() {
+        assert_eq!(
+            DlaBuilder::new().attraction_radius(3).build().unwrap_err(),
+            DlaError::AttractionRadiusTooSmall { radius: 3, min: 4 }
+        );
+        assert_eq!(
+            DlaBuilder::new()
+                .species(vec![Species::new(8), Species::new(2)])
+                .build()
+                .unwrap_err(),
+            DlaError::AttractionRadiusTooSmall { radius: 2, min: 4 }
+        );
+    }
+
+    #[test]
+    fn stickiness_must_be_a_non_zero_probability() {
+        assert_eq!(
+            DlaBuilder::new().stickiness(1.5).build().unwrap_err(),
+            DlaError::InvalidStickiness(1.5)
+        );
+        assert_eq!(
+            DlaBuilder::new().stickiness(0.0).build().unwrap_err(),
+            DlaError::NeverSticks(None)
+        );
+    }
+
+    #[test]
+    fn species_must_stick_to_a_growing_species() {
+        let sticky_to = |stickiness: Vec<f64>| Species {
+            stickiness,
+            ..Species::new(8)
+        };
+        assert_eq!(
+            DlaBuilder::new()
+                .species(vec![sticky_to(vec![0.0, 1.0]), sticky_to(vec![0.0, 1.0])])
+                .build()
+                .unwrap_err(),
+            DlaError::NeverSticks(Some(0))
+        );
+        // the second species only sticks to itself and never grows
+        assert_eq!(
+            DlaBuilder::new()
+                .species(vec![Species::new(8), sticky_to(vec![0.0, 1.0])])
+                .build()
+                .unwrap_err(),
+            DlaError::NeverSticks(Some(1))
+        );
+        // but it's fine once it can stick to the first one, which can then
+        // stick to the second one
+        assert!(DlaBuilder::new()
+            .species(vec![sticky_to(vec![0.0, 1.0]), sticky_to(vec![0.5, 0.0])])
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn anisotropy_strength_must_be_a_probability() {
+        let anisotropy = |strength: f64| Some(Anisotropy::new(Symmetry::Cubic, strength));
+        assert_eq!(
+            DlaBuilder::new()
+                .anisotropy(anisotropy(1.5))
+                .build()
+                .unwrap_err(),
+            DlaError::InvalidAnisotropyStrength(1.5)
+        );
+        assert_eq!(
+            DlaBuilder::new()
+                .anisotropy(anisotropy(-0.1))
+                .build()
+                .unwrap_err(),
+            DlaError::InvalidAnisotropyStrength(-0.1)
+        );
+        assert!(matches!(
+            DlaBuilder::new()
+                .anisotropy(anisotropy(f64::NAN))
+                .build()
+                .unwrap_err(),
+            DlaError::InvalidAnisotropyStrength(_)
+        ));
+        assert!(DlaBuilder::new()
+            .anisotropy(anisotropy(1.0))
+            .build()
+            .is_ok());
+    }
+
+    #[test]
+    fn attractors_are_validated() {
+        let attractor = |pull: f64, range: f64| Attractor {
+            shape: Shape::Sphere {
+                center: Vec3::new(20, 0, 0),
+                radius: 2,
+            },
+            pull,
+            stickiness: 1.0,
+            range,
+        };
+        assert_eq!(
+            DlaBuilder::new()
+                .attractor(attractor(1.5, 1.0))
+                .build()
+                .unwrap_err(),
+            DlaError::InvalidAttractorPull(1.5)
+        );
+        assert_eq!(
+            DlaBuilder::new()
+                .attractor(attractor(0.5, -1.0))
+                .build()
+                .unwrap_err(),
+            DlaError::InvalidAttractorRange(-1.0)
+        );
+        assert!(matches!(
+            DlaBuilder::new()
+                .attractor(attractor(0.5, f64::NAN))
+                .build()
+                .unwrap_err(),
+            DlaError::InvalidAttractorRange(_)
+        ));
+    }
+
+    #[test]
+    fn seeds_must_be_free() {
+        let origin = Vec3::new(0, 0, 0);
+        let around = |r: i64| Bbox::new(origin - r).expand(origin + r);
+        assert_eq!(
+            DlaBuilder::new()
+                .obstacle(Shape::Box(around(2)))
+                .build()
+                .unwrap_err(),
+            DlaError::SeedNotFree(origin)
+        );
+        let far = Bbox::new(Vec3::new(100, 100, 100)).expand(Vec3::new(110, 110, 110));
+        assert_eq!(
+            DlaBuilder::new()
+                .container(Shape::Box(far))
+                .build()
+                .unwrap_err(),
+            DlaError::SeedNotFree(origin)
+        );
+    }
+
+    #[test]
+    fn spawn_area_must_have_a_free_cell() {
+        let origin = Vec3::new(0, 0, 0);
+        let around = |r: i64| Bbox::new(origin - r).expand(origin + r);
+        assert_eq!(
+            DlaBuilder::new()
+                .spawn_radius(1000)
+                .container(Shape::Box(around(0)))
+                .build()
+                .unwrap_err(),
+            DlaError::NoFreeSpawnCell
+        );
+        assert_eq!(
+            DlaBuilder::new()
+                .spawn_radius(3)
+                .sink(Shape::Box(around(10)))
+                .build()
+                .unwrap_err(),
+            DlaError::NoFreeSpawnCell
+        );
+    }
+
+    #[test]
+    fn eden_rejects_the_walker_options() {
+        assert_eq!(
+            DlaBuilder::new()
+                .growth_model(GrowthModel::Eden)
+                .species(vec![Species::new(8), Species::new(8)])
+                .build()
+                .unwrap_err(),
+            DlaError::UnsupportedByEden("species")
+        );
+        assert_eq!(
+            DlaBuilder::new()
+                .growth_model(GrowthModel::Eden)
+                .anisotropy(Some(Anisotropy::new(Symmetry::Cubic, 0.5)))
+                .build()
+                .unwrap_err(),
+            DlaError::UnsupportedByEden("anisotropy")
+        );
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = DlaBuilder::new()
+            .attraction_radius(1)
+            .growth_model(GrowthModel::Eden)
+            .build()
+            .unwrap();
+        dla.add(&mut rng);
+        assert_eq!(dla.len(), 2);
+    }
+
+    #[test]
+    fn spawn_ratios_are_validated() {
+        let spawning = |spawn_ratio: f64| Species {
+            spawn_ratio,
+            ..Species::new(8)
+        };
+        let build = |ratios: &[f64]| {
+            DlaBuilder::new()
+                .species(ratios.iter().map(|r| spawning(*r)).collect())
+                .build()
+        };
+
+        assert_eq!(
+            build(&[1.0, -1.0]).unwrap_err(),
+            DlaError::InvalidSpawnRatio(-1.0)
+        );
+        assert_eq!(
+            build(&[f64::INFINITY, 1.0]).unwrap_err(),
+            DlaError::InvalidSpawnRatio(f64::INFINITY)
+        );
+        assert!(matches!(
+            build(&[1.0, f64::NAN]).unwrap_err(),
+            DlaError::InvalidSpawnRatio(_)
+        ));
+        assert_eq!(
+            build(&[f64::MAX, f64::MAX]).unwrap_err(),
+            DlaError::InvalidSpawnRatio(f64::INFINITY)
+        );
+        assert_eq!(build(&[0.0, 0.0]).unwrap_err(), DlaError::NoSpawnRatio);
+        assert_eq!(build(&[0.0]).unwrap_err(), DlaError::NoSpawnRatio);
+
+        assert!(build(&[0.0, 1.0]).is_ok());
+        assert!(build(&[]).is_ok());
+    }
+
+    #[test]
+    fn shapes_must_not_have_negative_sizes() {
+        let sphere = Shape::Sphere {
+            center: Vec3::new(20, 0, 0),
+            radius: -2,
+        };
+        let negative = DlaError::NegativeShapeSize {
+            size: "radius",
+            value: -2,
+        };
+
+        assert_eq!(
+            DlaBuilder::new()
+                .obstacle(sphere.clone())
+                .build()
+                .unwrap_err(),
+            negative
+        );
+        assert_eq!(
+            DlaBuilder::new().sink(sphere.clone()).build().unwrap_err(),
+            negative
+        );
+        assert_eq!(
+            DlaBuilder::new()
+                .attractor(Attractor {
+                    shape: sphere,
+                    pull: 0.5,
+                    stickiness: 1.0,
+                    range: 1.0,
+                })
+                .build()
+                .unwrap_err(),
+            negative
+        );
+    }
+
+    #[test]
+    fn only_diffusing_walkers_can_be_pulled() {
+        let attractor = |pull: f64| Attractor {
+            shape: Shape::Sphere {
+                center: Vec3::new(20, 0, 0),
+                radius: 2,
+            },
+            pull,
+            stickiness: 0.5,
+            range: 5.0,
+        };
+
+        for model in [
+            GrowthModel::Ballistic,
+            GrowthModel::Lattice(Connectivity::Six),
+        ] {
+            assert_eq!(
+                DlaBuilder::new()
+                    .growth_model(model)
+                    .attractor(attractor(0.3))
+                    .build()
+                    .unwrap_err(),
+                DlaError::AttractorPullUnsupported(model)
+            );
+
+            // the attractor stickiness alone is fine
+            assert!(DlaBuilder::new()
+                .growth_model(model)
+                .attractor(attractor(0.0))
+                .build()
+                .is_ok());
+        }
+
+        assert!(DlaBuilder::new().attractor(attractor(0.3)).build().is_ok());
+    }
+}
@@ -0,0 +1,430 @@
+use hashbrown::HashMap;
+
+use crate::geo::{Axis, Bbox, Transform, Vec3};
+use crate::species::{Species, SpeciesId, MAX_SPECIES};
+use crate::{Dla, DlaBuilder, DlaError};
+
+impl Dla {
+    /// Create a DLA made of the given cells, each with its own species, that
+    /// can keep growing with the given parameters. It fails if there are no
+    /// cells, if any of them is beyond `MAX_COORD` or if a species id is not
+    /// below `MAX_SPECIES`.
+    ///
+    /// The DLA gets a default species for each species id found in the cells.
+    pub fn from_cells(
+        spawn_radius: u32,
+        attraction_radius: u16,
+        cells: impl IntoIterator<Item = (Vec3, SpeciesId)>,
+    ) -> Result<Self, DlaError> {
+        let cells = cells.into_iter().collect::<Vec<_>>();
+
+        let species = cells.iter().map(|(_, s)| *s).max().unwrap_or(0);
+        if species >= MAX_SPECIES {
+            return Err(DlaError::SpeciesOutOfRange(species));
+        }
+
+        let mut builder = DlaBuilder::new()
+            .spawn_radius(spawn_radius)
+            .attraction_radius(attraction_radius);
+        if species > 0 {
+            builder = builder.species(vec![Species::new(attraction_radius); species + 1]);
+        }
+
+        Dla::build_with_cells(builder, cells)
+    }
+
+    /// Build a DLA with `builder` made of the given cells instead of its
+    /// seeds. It fails if the builder does, if any cell is beyond
+    /// `MAX_COORD` or if it belongs to a species the built DLA doesn't have.
+    pub(crate) fn build_with_cells(
+        builder: DlaBuilder,
+        cells: Vec<(Vec3, SpeciesId)>,
+    ) -> Result<Self, DlaError> {
+        if let Some((c, _)) = cells.iter().find(|(c, _)| !c.is_bounded()) {
+            return Err(DlaError::CoordinateOverflow(*c));
+        }
+
+        let mut dla = builder.seeds(cells.first().map(|c| c.0)).build()?;
+
+        let count = dla.species().len().max(1);
+        if let Some(&(cell, species)) = cells.iter().find(|(_, s)| *s >= count) {
+            return Err(DlaError::UnknownSpecies { cell, species });
+        }
+
+        dla.replace_cells(cells);
+        Ok(dla)
+    }
+
+    /// Move all the cells by `d`, failing without moving anything if a cell
+    /// would end up beyond `MAX_COORD`.
+    pub fn translate(&mut self, d: Vec3) -> Result<(), DlaError> {
+        self.try_map_cells(|c| saturating_add(c, d))
+    }
+
+    /// Rotate the cells by the given number of quarter turns counterclockwise
+    /// around the axis passing through the origin. Unlike `transform` the
+    /// rotation is exact.
+    pub fn rotate90(&mut self, axis: Axis, turns: i32) {
+        let turn = |p: Vec3| match axis {
+            Axis::X => Vec3::new(p.x, -p.z, p.y),
+            Axis::Y => Vec3::new(p.z, p.y, -p.x),
+            Axis::Z => Vec3::new(-p.y, p.x, p.z),
+        };
+
+        let turns = turns.rem_euclid(4);
+        self.map_cells(|c| (0..turns).fold(c, |c, _| turn(c)));
+    }
+
+    /// Mirror the cells across the plane perpendicular to the axis passing
+    /// through the origin.
+    pub fn mirror(&mut self, axis: Axis) {
+        self.map_cells(|c| match axis {
+            Axis::X => Vec3::new(-c.x, c.y, c.z),
+            Axis::Y => Vec3::new(c.x, -c.y, c.z),
+            Axis::Z => Vec3::new(c.x, c.y, -c.z),
+        });
+    }
+
+    /// Apply an arbitrary transform to the cells resampling them on the
+    /// lattice.
+    ///
+    /// Every cell is moved to the lattice point closest to its image and
+    /// every lattice point around it whose preimage rounds to a cell is
+    /// occupied too, so that scaling up or rotating doesn't leave holes in the
+    /// DLA. Cells ending up on the same lattice point are merged. It fails
+    /// without changing anything if a cell would end up beyond `MAX_COORD`.
+    pub fn transform(&mut self, t: &Transform) -> Result<(), DlaError> {
+        let inverse = t.inverse();
+
+        let mut cells = Vec::with_capacity(self.len());
+        for &c in self.cells() {
+            let species = self.species_of(c);
+            let image = t.apply_lattice(c);
+            if !image.is_bounded() {
+                return Err(DlaError::CoordinateOverflow(image));
+            }
+            cells.push((image, species));
+
+            if let Some(inverse) = &inverse {
+                let around = t.apply_bbox(&Bbox::new(c - 1).expand(c + 1));
+                for corner in [around.lower(), around.upper()] {
+                    if !corner.is_bounded() {
+                        return Err(DlaError::CoordinateOverflow(corner));
+                    }
+                }
+                for x in around.lower().x..=around.upper().x {
+                    for y in around.lower().y..=around.upper().y {
+                        for z in around.lower().z..=around.upper().z {
+                            let q = Vec3::new(x, y, z);
+                            if inverse.apply_lattice(q) == c {
+                                cells.push((q, species));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        self.replace_cells(cells);
+        Ok(())
+    }
+
+    /// Add the cells of `other` to this DLA keeping their species. Cells
+    /// occupied in both keep the species they have in this DLA, while the
+    /// parameters of `other` are ignored except for the species this DLA
+    /// doesn't have yet, which are added to it.
+    pub fn merge(&mut self, other: &Dla) {
+        let needed = other
+            .cell_species
+            .values()
+            .map(|s| s + 1)
+            .chain(std::iter::once(other.species.len()))
+            .max()
+            .unwrap_or(0);
+        if needed > self.species.len().max(1) {
+            let default = Species::new(self.attraction_radius as u16);
+            if self.species.is_empty() {
+                self.species.push(default.clone());
+            }
+            for s in self.species.len()..needed {
+                let species = other.species.get(s).unwrap_or(&default);
+                self.species.push(species.clone());
+            }
+        }
+
+        for &c in other.cells() {
+            let species = other.species_of(c);
+            if species != 0 && !self.contains(c) {
+                self.cell_species.insert(c, species);
+            }
+        }
+
+        self.cells.extend(other.cells().copied());
+        self.bbox = self.bbox.union(&other.bbox);
+        self.perimeter = None;
+    }
+
+    /// Merge all the given aggregates into the first one, return `None` if
+    /// there are none.
+    pub fn merge_all(dlas: impl IntoIterator<Item = Dla>) -> Option<Dla> {
+        let mut dlas = dlas.into_iter();
+        let mut merged = dlas.next()?;
+        for dla in dlas {
+            merged.merge(&dla);
+        }
+
+        Some(merged)
+    }
+
+    /// A DLA made of `counts` copies of this one along each axis, each
+    /// `spacing` away from the previous one. It fails if a count is 0 or if
+    /// a copy would end up beyond `MAX_COORD`.
+    pub fn tile(&self, counts: [u32; 3], spacing: Vec3) -> Result<Dla, DlaError> {
+        if counts.contains(&0) {
+            return Err(DlaError::EmptyTiling(counts));
+        }
+        let [nx, ny, nz] = counts.map(i64::from);
+
+        let offset = |x: i64, y: i64, z: i64| {
+            Vec3::new(
+                spacing.x.saturating_mul(x),
+                spacing.y.saturating_mul(y),
+                spacing.z.saturating_mul(z),
+            )
+        };
+
+        // the farthest copy is checked upfront so that huge counts fail before
+        // allocating anything
+        let last = offset(nx - 1, ny - 1, nz - 1);
+        for corner in [self.bbox.lower(), self.bbox.upper()] {
+            let moved = saturating_add(corner, last);
+            if !moved.is_bounded() {
+                return Err(DlaError::CoordinateOverflow(moved));
+            }
+        }
+
+        let mut cells = vec![];
+        for x in 0..nx {
+            for y in 0..ny {
+                for z in 0..nz {
+                    let d = offset(x, y, z);
+                    cells.extend(self.cells().map(|&c| (c + d, self.species_of(c))));
+                }
+            }
+        }
+
+        let mut tiled = self.clone();
+        tiled.replace_cells(cells);
+        Ok(tiled)
+    }
+
+    fn map_cells(&mut self, f: impl Fn(Vec3) -> Vec3) {
+        self.try_map_cells(f)
+            .expect("rotations and mirroring keep the cells bounded");
+    }
+
+    /// Map all the cells with `f`, failing without changing anything if a
+    /// cell is mapped beyond `MAX_COORD`.
+    fn try_map_cells(&mut self, f: impl Fn(Vec3) -> Vec3) -> Result<(), DlaError> {
+        let mut cells = Vec::with_capacity(self.len());
+        for &c in self.cells() {
+            let m = f(c);
+            if !m.is_bounded() {
+                return Err(DlaError::CoordinateOverflow(m));
+            }
+            cells.push((m, self.species_of(c)));
+        }
+
+        self.replace_cells(cells);
+        Ok(())
+    }
+
+    /// Replace all the cells of the DLA, there must be at least one.
+    fn replace_cells(&mut self, cells: Vec<(Vec3, SpeciesId)>) {
+        let cells = cells
+            .into_iter()
+            .map(|(c, s)| (self.domain.wrap(c), s))
+            .collect::<Vec<_>>();
+
+        self.bbox = cells
+            .iter()
+            .fold(Bbox::new(cells[0].0), |b, (c, _)| b.expand(*c));
+        self.cell_species = cells
+            .iter()
+            .filter(|(_, s)| *s != 0)
+            .copied()
+            .collect::<HashMap<_, _>>();
+        self.cells = cells.into_iter().map(|(c, _)| c).collect();
+
+        // the sites hit so far and the Eden perimeter refer to the old cells
+        self.hits.clear();
+        self.perimeter = None;
+    }
+}
+
+fn saturating_add(a: Vec3, b: Vec3) -> Vec3 {
+    Vec3::new(
+        a.x.saturating_add(b.x),
+        a.y.saturating_add(b.y),
+        a.z.saturating_add(b.z),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::geo::{Vec3f, MAX_COORD};
+
+    #[test]
+    fn lattice_operations_are_exact() {
+        let cells = vec![
+            (Vec3::new(0, 0, 0), 0),
+            (Vec3::new(1, 0, 0), 1),
+            (Vec3::new(1, 2, 0), 0),
+            (Vec3::new(1, 2, 3), 1),
+        ];
+        let dla = Dla::from_cells(10, 8, cells.clone()).unwrap();
+        assert_eq!(dla.len(), 4);
+        assert_eq!(dla.species().len(), 2);
+        assert_eq!(dla.species_of(Vec3::new(1, 2, 3)), 1);
+
+        for axis in Axis::ALL {
+            let mut rotated = dla.clone();
+            rotated.rotate90(axis, 1);
+            let mut t = dla.clone();
+            t.transform(&Transform::rotate(
+                axis.unit().into(),
+                std::f64::consts::FRAC_PI_2,
+            ))
+            .unwrap();
+            assert_eq!(rotated.octree(), t.octree());
+
+            rotated.rotate90(axis, 3);
+            assert_eq!(rotated.octree(), dla.octree());
+
+            let mut mirrored = dla.clone();
+            mirrored.mirror(axis);
+            mirrored.mirror(axis);
+            assert_eq!(mirrored.octree(), dla.octree());
+        }
+
+        let mut moved = dla.clone();
+        moved.translate(Vec3::new(10, 0, 0)).unwrap();
+        assert!(moved.contains(Vec3::new(11, 2, 3)));
+        assert_eq!(moved.species_of(Vec3::new(11, 0, 0)), 1);
+        assert_eq!(moved.bbox().lower(), Vec3::new(10, 0, 0));
+
+        let merged = Dla::merge_all(vec![dla.clone(), moved, dla.clone()]).unwrap();
+        assert_eq!(merged.len(), 8);
+        assert_eq!(merged.species_of(Vec3::new(11, 2, 3)), 1);
+
+        let tiled = dla.tile([2, 1, 3], Vec3::new(5, 0, 5)).unwrap();
+        assert_eq!(tiled.len(), 24);
+        assert_eq!(tiled.bbox().upper(), Vec3::new(6, 2, 13));
+        assert_eq!(tiled.species_of(Vec3::new(6, 0, 10)), 1);
+
+        assert_eq!(
+            dla.tile([2, 0, 3], Vec3::new(5, 0, 5)).unwrap_err(),
+            DlaError::EmptyTiling([2, 0, 3])
+        );
+    }
+
+    #[test]
+    fn scaling_up_leaves_no_holes() {
+        let line = (0..10).map(|x| (Vec3::new(x, 0, 0), 0));
+        let mut dla = Dla::from_cells(10, 8, line).unwrap();
+
+        dla.transform(&Transform::scale(Vec3f::new(3.0, 1.0, 1.0)))
+            .unwrap();
+        assert!((0..=27).all(|x| dla.contains(Vec3::new(x, 0, 0))));
+
+        dla.transform(&Transform::scale(Vec3f::new(0.1, 1.0, 1.0)))
+            .unwrap();
+        let mut cells = dla.cells().copied().collect::<Vec<_>>();
+        cells.sort_by_key(|c| c.x);
+        assert_eq!(
+            cells,
+            (0..4).map(|x| Vec3::new(x, 0, 0)).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn invalid_cells_are_rejected() {
+        let far = Vec3::new(MAX_COORD + 1, 0, 0);
+        let origin = Vec3::new(0, 0, 0);
+
+        assert_eq!(
+            Dla::from_cells(10, 8, vec![]).err(),
+            Some(DlaError::NoSeeds)
+        );
+        assert_eq!(
+            Dla::from_cells(10, 8, vec![(origin, 0), (far, 0)]).err(),
+            Some(DlaError::CoordinateOverflow(far))
+        );
+        assert_eq!(
+            Dla::from_cells(10, 8, vec![(origin, MAX_SPECIES)]).err(),
+            Some(DlaError::SpeciesOutOfRange(MAX_SPECIES))
+        );
+        assert_eq!(
+            Dla::from_cells(10, 8, vec![(origin, usize::MAX)]).err(),
+            Some(DlaError::SpeciesOutOfRange(usize::MAX))
+        );
+        assert!(Dla::from_cells(10, 8, vec![(origin, MAX_SPECIES - 1)]).is_ok());
+    }
+
+    #[test]
+    fn moving_beyond_the_limit_fails() {
+        let cells = (0..3).map(|x| (Vec3::new(x, 0, 0), 0));
+        let mut dla = Dla::from_cells(10, 8, cells).unwrap();
+        let original = dla.clone();
+
+        assert!(matches!(
+            dla.translate(Vec3::new(MAX_COORD - 1, 0, 0)),
+            Err(DlaError::CoordinateOverflow(_))
+        ));
+        assert!(matches!(
+            dla.translate(Vec3::new(0, i64::MIN, 0)),
+            Err(DlaError::CoordinateOverflow(_))
+        ));
+        assert!(matches!(
+            dla.transform(&Transform::scale(Vec3f::new(1e12, 1.0, 1.0))),
+            Err(DlaError::CoordinateOverflow(_))
+        ));
+        assert!(matches!(
+            dla.tile([u32::MAX, 1, 1], Vec3::new(10, 0, 0)),
+            Err(DlaError::CoordinateOverflow(_))
+        ));
+        assert!(matches!(
+            dla.tile([2, 1, 1], Vec3::new(i64::MAX, 0, 0)),
+            Err(DlaError::CoordinateOverflow(_))
+        ));
+
+        assert_eq!(
+            dla.cells().collect::<Vec<_>>(),
+            original.cells().collect::<Vec<_>>()
+        );
+        assert_eq!(dla.bbox(), original.bbox());
+    }
+
+    #[test]
+    fn merging_adds_the_missing_species() {
+        let mut single = Dla::from_cells(10, 8, vec![(Vec3::new(0, 0, 0), 0)]).unwrap();
+        let many = Dla::from_cells(
+            10,
+            8,
+            vec![(Vec3::new(5, 0, 0), 0), (Vec3::new(6, 0, 0), 2)],
+        )
+        .unwrap();
+
+        single.merge(&many);
+        assert_eq!(single.species().len(), 3);
+        assert_eq!(single.species_of(Vec3::new(6, 0, 0)), 2);
+        assert_eq!(single.species_of(Vec3::new(0, 0, 0)), 0);
+
+        // species already there are kept
+        let mut more = many.clone();
+        more.merge(&single);
+        assert_eq!(more.species().len(), 3);
+    }
+}
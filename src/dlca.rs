@@ -0,0 +1,308 @@
+use rand::Rng;
+
+use crate::domain::Domain;
+use crate::geo::{Bbox, Vec3};
+use crate::octree::Octree;
+use crate::{Dla, DlaBuilder, DlaError};
+
+/// Diffusion limited cluster-cluster aggregation.
+///
+/// Many clusters, initially made of a single particle, randomly diffuse on a
+/// lattice inside a periodic box and merge as soon as they touch each other.
+///
+/// Distances between clusters are computed using the minimum image
+/// convention, hence clusters should stay smaller than half the box.
+///
+/// Every cluster is equally likely to move at each step whatever its size,
+/// i.e. all the clusters share the same diffusion coefficient. Physical
+/// clusters slow down as they grow, which makes the actual aggregates a bit
+/// more open than the ones simulated here.
+#[derive(Debug, Clone)]
+pub struct Dlca {
+    size: i64,
+    domain: Domain,
+    clusters: Vec<Cluster>,
+    steps: u64,
+}
+
+/// A rigid group of particles moving together.
+///
+/// The cells are stored relative to the cluster offset so that moving a
+/// cluster doesn't require touching its cells at all.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    offset: Vec3,
+    cells: Octree,
+    bbox: Bbox,
+}
+
+const DIRECTIONS: [Vec3; 6] = [
+    Vec3::new(1, 0, 0),
+    Vec3::new(-1, 0, 0),
+    Vec3::new(0, 1, 0),
+    Vec3::new(0, -1, 0),
+    Vec3::new(0, 0, 1),
+    Vec3::new(0, 0, -1),
+];
+
+impl Dlca {
+    /// Create a new simulation with the given number of particles randomly
+    /// placed in a periodic box of the given size. Return `None` if there are
+    /// no particles, if they don't fit in the box or if the box is too big.
+    pub fn new<R: Rng>(size: u32, particles: usize, rng: &mut R) -> Option<Self> {
+        let size = i64::from(size);
+        if particles == 0 || particles as u128 > (size as u128).pow(3) {
+            return None;
+        }
+        let domain = Domain::periodic(Vec3::new(0, 0, 0), size)?;
+
+        let mut occupied = hashbrown::HashSet::with_capacity(particles);
+        while occupied.len() < particles {
+            occupied.insert(Vec3::new(
+                rng.gen_range(0..size),
+                rng.gen_range(0..size),
+                rng.gen_range(0..size),
+            ));
+        }
+
+        let mut dlca = Dlca {
+            size,
+            domain,
+            clusters: vec![],
+            steps: 0,
+        };
+
+        // particles might be placed right next to each other, merge them
+        // straight away
+        for p in occupied {
+            dlca.clusters.push(Cluster::new(p));
+
+            let last = dlca.clusters.len() - 1;
+            dlca.merge_touching(last);
+        }
+
+        Some(dlca)
+    }
+
+    pub fn size(&self) -> i64 {
+        self.size
+    }
+
+    /// The periodic box the clusters diffuse in.
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+
+    pub fn clusters(&self) -> &[Cluster] {
+        &self.clusters
+    }
+
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    /// Whether all the particles have been merged in a single cluster.
+    pub fn is_done(&self) -> bool {
+        self.clusters.len() <= 1
+    }
+
+    /// Move a cluster picked uniformly at random by one step in a random
+    /// direction, merging it with all the clusters it touches. Return whether
+    /// any merge happened.
+    pub fn step<R: Rng>(&mut self, rng: &mut R) -> bool {
+        if self.is_done() {
+            return false;
+        }
+
+        let i = rng.gen_range(0..self.clusters.len());
+        let d = DIRECTIONS[rng.gen_range(0..DIRECTIONS.len())];
+
+        let c = &mut self.clusters[i];
+        c.offset = self.domain.wrap(c.offset + d);
+        self.steps += 1;
+
+        let n = self.clusters.len();
+        self.merge_touching(i);
+        self.clusters.len() != n
+    }
+
+    /// Keep moving clusters until they all merge into one or until
+    /// `max_steps` steps have been taken. Return whether all the clusters
+    /// merged.
+    pub fn run<R: Rng>(&mut self, rng: &mut R, max_steps: u64) -> bool {
+        for _ in 0..max_steps {
+            if self.is_done() {
+                break;
+            }
+
+            self.step(rng);
+        }
+
+        self.is_done()
+    }
+
+    /// Collect the cells of all the clusters in a DLA with the given attraction
+    /// radius. While many clusters are left, their cells are wrapped in the
+    /// box so that they don't overlap and the DLA keeps growing in the same
+    /// periodic box.
+    pub fn to_dla(&self, attraction_radius: u16) -> Result<Dla, DlaError> {
+        let cells = self.clusters.iter().flat_map(|c| c.cells());
+
+        let builder = DlaBuilder::new().attraction_radius(attraction_radius);
+        let builder = if self.is_done() {
+            builder.seeds(cells.collect::<Vec<_>>())
+        } else {
+            builder
+                .seeds(cells.map(|p| self.domain.wrap(p)).collect::<Vec<_>>())
+                .domain(self.domain.clone())
+        };
+
+        builder.build()
+    }
+
+    /// Merge all the clusters touching the i-th cluster into it.
+    fn merge_touching(&mut self, mut i: usize) {
+        let mut j = 0;
+        while j < self.clusters.len() {
+            if j == i || !self.touching(i, j) {
+                j += 1;
+                continue;
+            }
+
+            // always merge the smaller cluster into the bigger one
+            let (big, small) = if self.clusters[i].len() >= self.clusters[j].len() {
+                (i, j)
+            } else {
+                (j, i)
+            };
+
+            let small_cluster = self.clusters.swap_remove(small);
+            let big = if big == self.clusters.len() {
+                small
+            } else {
+                big
+            };
+            self.absorb(big, &small_cluster);
+
+            // the merged cluster might be touching clusters already checked,
+            // start over
+            i = big;
+            j = 0;
+        }
+    }
+
+    fn touching(&self, i: usize, j: usize) -> bool {
+        let (a, b) = (&self.clusters[i], &self.clusters[j]);
+
+        // quick rejection using the bounding boxes
+        let a_center = a.offset + a.bbox.center();
+        let b_center = b.offset + b.bbox.center();
+        let d = self.domain.min_image(a_center - b_center);
+        let extent = (a.bbox.dimensions() + b.bbox.dimensions()) / 2 + 2;
+        if d.x.abs() > extent.x || d.y.abs() > extent.y || d.z.abs() > extent.z {
+            return false;
+        }
+
+        a.cells.iter().any(|p| {
+            let q = self.local_position(b, a.offset + *p);
+            b.cells.nearest(q).is_some_and(|(_, d2)| d2 <= 1)
+        })
+    }
+
+    fn absorb(&mut self, i: usize, other: &Cluster) {
+        let cells = other
+            .cells
+            .iter()
+            .map(|p| self.local_position(&self.clusters[i], other.offset + *p))
+            .collect::<Vec<_>>();
+
+        let c = &mut self.clusters[i];
+        for p in cells {
+            c.cells.add(p);
+            c.bbox = c.bbox.expand(p);
+        }
+    }
+
+    /// Position of the world point `p` relative to the cluster, picking the
+    /// periodic image closest to the cluster.
+    fn local_position(&self, c: &Cluster, p: Vec3) -> Vec3 {
+        let center = c.bbox.center();
+        center + self.domain.min_image(p - c.offset - center)
+    }
+}
+
+impl Cluster {
+    fn new(p: Vec3) -> Self {
+        let mut cells = Octree::new();
+        cells.add(Vec3::new(0, 0, 0));
+
+        Cluster {
+            offset: p,
+            cells,
+            bbox: Bbox::new(Vec3::new(0, 0, 0)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The cells of the cluster in world coordinates. The cells are not
+    /// wrapped in the periodic box so that the cluster stays connected.
+    pub fn cells(&self) -> impl Iterator<Item = Vec3> + '_ {
+        self.cells.iter().map(move |p| self.offset + *p)
+    }
+
+    /// The bounding box of the cluster in world coordinates.
+    pub fn bbox(&self) -> Bbox {
+        Bbox::new(self.offset + self.bbox.lower()).expand(self.offset + self.bbox.upper())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn clusters_eventually_merge_into_one() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dlca = Dlca::new(20, 50, &mut rng).unwrap();
+
+        assert!(dlca.run(&mut rng, 10_000_000));
+        assert_eq!(dlca.clusters().len(), 1);
+
+        let cluster = &dlca.clusters()[0];
+        assert_eq!(cluster.len(), 50);
+
+        // every cell must be connected to at least another one
+        let cells = cluster.cells().collect::<Vec<_>>();
+        for c in &cells {
+            assert!(cells.iter().any(|o| o.dist2(*c) == 1));
+        }
+
+        let dla = dlca.to_dla(8).unwrap();
+        assert_eq!(dla.len(), 50);
+        assert_eq!(dla.domain(), &Domain::Unbounded);
+    }
+
+    #[test]
+    fn unfinished_clusters_stay_in_the_box() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dlca = Dlca::new(30, 40, &mut rng).unwrap();
+        for _ in 0..100 {
+            dlca.step(&mut rng);
+        }
+        assert!(!dlca.is_done());
+
+        let dla = dlca.to_dla(8).unwrap();
+        assert_eq!(dla.len(), 40);
+        assert_eq!(dla.domain(), dlca.domain());
+        assert!(dla.cells().all(|c| dlca.domain().wrap(*c) == *c));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::geo::{Bbox, Vec3};
+
+/// The region of space the DLA grows in.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Domain {
+    /// Unbounded space, walkers that wander too far from the DLA are
+    /// respawned close to it.
+    #[default]
+    Unbounded,
+
+    /// A periodic box, walkers leaving the box from a face come back from the
+    /// opposite one and distances follow the minimum image convention.
+    ///
+    /// The box should be at least twice as big as the DLA, otherwise its
+    /// periodic images start interacting with each other.
+    Periodic(Bbox),
+}
+
+impl Domain {
+    /// A periodic box of the given size whose lower corner is `lower`. Return
+    /// `None` if the size is not positive or if the box goes beyond the
+    /// coordinates that can be safely handled, see `Vec3::is_bounded`.
+    pub fn periodic(lower: Vec3, size: i64) -> Option<Self> {
+        if size <= 0 {
+            return None;
+        }
+
+        let upper = lower.checked_add(Vec3::new(size - 1, size - 1, size - 1))?;
+        if !lower.is_bounded() || !upper.is_bounded() {
+            return None;
+        }
+
+        Some(Domain::Periodic(Bbox::new(lower).expand(upper)))
+    }
+
+    /// Map the point to its image inside the domain.
+    pub fn wrap(&self, p: Vec3) -> Vec3 {
+        match self {
+            Domain::Unbounded => p,
+            Domain::Periodic(bbox) => {
+                let d = p - bbox.lower();
+                let size = bbox.dimensions() + 1;
+
+                bbox.lower()
+                    + Vec3::new(
+                        d.x.rem_euclid(size.x),
+                        d.y.rem_euclid(size.y),
+                        d.z.rem_euclid(size.z),
+                    )
+            }
+        }
+    }
+
+    /// Shortest vector equivalent to `d` in the domain.
+    pub fn min_image(&self, d: Vec3) -> Vec3 {
+        match self {
+            Domain::Unbounded => d,
+            Domain::Periodic(bbox) => {
+                let size = bbox.dimensions() + 1;
+                let w = |x: i64, size: i64| {
+                    let x = x.rem_euclid(size);
+                    if x * 2 > size {
+                        x - size
+                    } else {
+                        x
+                    }
+                };
+
+                Vec3::new(w(d.x, size.x), w(d.y, size.y), w(d.z, size.z))
+            }
+        }
+    }
+
+    /// Squared distance between two points in the domain.
+    pub fn dist2(&self, a: Vec3, b: Vec3) -> i64 {
+        self.min_image(b - a).norm2()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::Dla;
+
+    #[test]
+    fn periodic_domain_wraps_around() {
+        let domain = Domain::periodic(Vec3::new(-5, -5, -5), 10).unwrap();
+
+        assert_eq!(domain.wrap(Vec3::new(0, 5, -6)), Vec3::new(0, -5, 4));
+        assert_eq!(domain.wrap(Vec3::new(4, 4, 4)), Vec3::new(4, 4, 4));
+        assert_eq!(domain.dist2(Vec3::new(-5, 0, 0), Vec3::new(4, 0, 0)), 1);
+        assert_eq!(domain.min_image(Vec3::new(7, -7, 5)), Vec3::new(-3, 3, 5));
+
+        assert_eq!(
+            Domain::Unbounded.dist2(Vec3::new(-5, 0, 0), Vec3::new(4, 0, 0)),
+            81
+        );
+
+        assert_eq!(Domain::periodic(Vec3::new(0, 0, 0), 0), None);
+        assert_eq!(Domain::periodic(Vec3::new(0, 0, 0), -3), None);
+        assert_eq!(Domain::periodic(Vec3::new(0, 0, 0), i64::MAX), None);
+        assert_eq!(
+            Domain::periodic(Vec3::new(0, 0, 0), 1),
+            Some(Domain::Periodic(Bbox::new(Vec3::new(0, 0, 0))))
+        );
+    }
+
+    #[test]
+    fn periodic_dla_stays_inside_the_box() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let domain = Domain::periodic(Vec3::new(-10, -10, -10), 20).unwrap();
+
+        let mut dla = Dla::new(10, 4, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.set_domain(domain.clone());
+
+        for _ in 0..300 {
+            dla.add(&mut rng);
+        }
+
+        let Domain::Periodic(bbox) = domain else {
+            unreachable!()
+        };
+        assert!(dla.cells().all(|c| bbox.contains(*c)));
+        assert_eq!(
+            dla.octree().nearest_in(Vec3::new(-10, 0, 0), dla.domain()),
+            dla.octree().nearest_in(Vec3::new(10, 0, 0), dla.domain())
+        );
+    }
+}
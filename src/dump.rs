@@ -0,0 +1,1100 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::anisotropy::{Anisotropy, Symmetry};
+use crate::geo::{Axis, Bbox, Vec3, Vec3f};
+use crate::growth::{Connectivity, GrowthModel};
+use crate::shape::{Mesh, Shape};
+use crate::species::{Species, SpeciesId, MAX_SPECIES};
+use crate::{Attractor, Dla, DlaBuilder, Domain};
+
+/// The bytes every dump starts with.
+pub const MAGIC: [u8; 4] = *b"DLAD";
+
+/// The version of the format written by `Writer`, readers refuse newer ones.
+pub const VERSION: u16 = 1;
+
+const FLAG_PARENTS: u16 = 1;
+const FLAG_SEED: u16 = 1 << 1;
+const FLAG_BBOX: u16 = 1 << 2;
+const FLAG_SPECIES: u16 = 1 << 3;
+const FLAG_DOMAIN: u16 = 1 << 4;
+const FLAG_ANISOTROPY: u16 = 1 << 5;
+const FLAG_SDF: u16 = 1 << 6;
+const KNOWN_FLAGS: u16 =
+    FLAG_PARENTS | FLAG_SEED | FLAG_BBOX | FLAG_SPECIES | FLAG_DOMAIN | FLAG_ANISOTROPY | FLAG_SDF;
+
+// the length and the bounding box sit at a fixed offset right after the
+// flags so that they can be patched once the cells have been written
+const PATCHABLE_START: u64 = 6;
+const UNKNOWN_LEN: u64 = u64::MAX;
+
+/// The header of a dump of a DLA: the parameters it was grown with and what
+/// to expect from its records.
+///
+/// On disk a dump is made of the magic bytes, the version and the header
+/// followed by the records of the cells in insertion order. Each record
+/// stores the difference from the previous cell as zigzag varints, the
+/// species of the cell if the DLA has many species and the distance from
+/// the index of its parent if parents are stored.
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub spawn_radius: u32,
+    pub attraction_radius: u16,
+    pub growth_model: GrowthModel,
+    pub species: Vec<Species>,
+    pub stickiness: f64,
+    pub anisotropy: Option<Anisotropy>,
+    pub domain: Domain,
+    pub obstacles: Vec<Shape>,
+    pub containers: Vec<Shape>,
+    pub attractors: Vec<Attractor>,
+    pub sinks: Vec<Shape>,
+
+    /// Whether the DLA has shapes defined by a signed distance function. They
+    /// are not stored since they're arbitrary code, hence `load` refuses to
+    /// restore such a DLA.
+    pub sdf: bool,
+
+    /// Seed of the random number generator used to grow the DLA, if known.
+    pub seed: Option<u64>,
+
+    /// Number of records, `None` if unknown in which case records are read
+    /// until the end of the stream.
+    pub len: Option<u64>,
+
+    /// Bounding box of all the cells, if known.
+    pub bbox: Option<Bbox>,
+
+    /// Whether records store the index of the cell they stuck to.
+    pub parents: bool,
+}
+
+/// A cell of the DLA as stored in a dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    pub cell: Vec3,
+    pub species: SpeciesId,
+
+    /// Index of the record of the cell this one stuck to, `None` for the
+    /// seeds or when parents are not stored.
+    pub parent: Option<u64>,
+}
+
+/// Streaming writer of a dump, records are written as soon as they're given.
+#[derive(Debug)]
+pub struct Writer<W: Write> {
+    out: W,
+    header: Header,
+    prev: Vec3,
+    written: u64,
+    bbox: Option<Bbox>,
+}
+
+/// Streaming reader of a dump, records are read one at a time by iterating
+/// over it.
+#[derive(Debug)]
+pub struct Reader<R: Read> {
+    input: R,
+    header: Header,
+    prev: Vec3,
+    read: u64,
+}
+
+impl Header {
+    /// The header describing the given DLA, parents are not stored.
+    pub fn of(dla: &Dla, seed: Option<u64>) -> Self {
+        let stored = |s: &&Shape| !matches!(s, Shape::Sdf(_));
+        let shapes = |shapes: &[Shape]| shapes.iter().filter(stored).cloned().collect();
+
+        Header {
+            spawn_radius: dla.spawn_radius() as u32,
+            attraction_radius: dla.attraction_radius() as u16,
+            growth_model: dla.growth_model(),
+            species: dla.species().to_vec(),
+            stickiness: dla.stickiness(),
+            anisotropy: dla.anisotropy().cloned(),
+            domain: dla.domain().clone(),
+            obstacles: shapes(dla.obstacles()),
+            containers: shapes(dla.containers()),
+            attractors: dla
+                .attractors()
+                .iter()
+                .filter(|a| stored(&&a.shape))
+                .cloned()
+                .collect(),
+            sinks: shapes(dla.sinks()),
+            sdf: [dla.obstacles(), dla.containers(), dla.sinks()]
+                .concat()
+                .iter()
+                .chain(dla.attractors().iter().map(|a| &a.shape))
+                .any(|s| !stored(&s)),
+            seed,
+            len: Some(dla.len() as u64),
+            bbox: Some(dla.bbox()),
+            parents: false,
+        }
+    }
+
+    fn has_species(&self) -> bool {
+        self.species.len() > 1
+    }
+
+    fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        out.write_all(&MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        self.write_patchable(out)?;
+
+        out.write_all(&self.seed.unwrap_or(0).to_le_bytes())?;
+        out.write_all(&self.spawn_radius.to_le_bytes())?;
+        out.write_all(&self.attraction_radius.to_le_bytes())?;
+
+        let (model, connectivity) = match self.growth_model {
+            GrowthModel::Diffusion => (0, 0),
+            GrowthModel::Ballistic => (1, 0),
+            GrowthModel::Eden => (2, 0),
+            GrowthModel::Lattice(Connectivity::Six) => (3, 6),
+            GrowthModel::Lattice(Connectivity::Eighteen) => (3, 18),
+            GrowthModel::Lattice(Connectivity::TwentySix) => (3, 26),
+        };
+        out.write_all(&[model, connectivity])?;
+
+        write_varint(out, self.species.len() as u64)?;
+        for s in &self.species {
+            out.write_all(&s.attraction_radius.to_le_bytes())?;
+            out.write_all(&s.spawn_ratio.to_le_bytes())?;
+            write_varint(out, s.stickiness.len() as u64)?;
+            for p in &s.stickiness {
+                out.write_all(&p.to_le_bytes())?;
+            }
+        }
+
+        out.write_all(&self.stickiness.to_le_bytes())?;
+        if let Some(a) = &self.anisotropy {
+            let symmetry: u8 = match a.symmetry {
+                Symmetry::Cubic => 0,
+                Symmetry::Hexagonal => 1,
+            };
+            out.write_all(&[symmetry])?;
+            out.write_all(&a.strength.to_le_bytes())?;
+            out.write_all(&a.hits.to_le_bytes())?;
+        }
+        if let Domain::Periodic(bbox) = &self.domain {
+            write_bbox(out, bbox)?;
+        }
+
+        for shapes in [&self.obstacles, &self.containers] {
+            write_shapes(out, shapes)?;
+        }
+        write_varint(out, self.attractors.len() as u64)?;
+        for a in &self.attractors {
+            write_shape(out, &a.shape)?;
+            for v in [a.pull, a.stickiness, a.range] {
+                out.write_all(&v.to_le_bytes())?;
+            }
+        }
+        write_shapes(out, &self.sinks)?;
+
+        Ok(())
+    }
+
+    /// Write the flags, the length and the bounding box, they always take the
+    /// same number of bytes.
+    fn write_patchable(&self, out: &mut impl Write) -> io::Result<()> {
+        let mut flags = 0;
+        for (set, flag) in [
+            (self.parents, FLAG_PARENTS),
+            (self.seed.is_some(), FLAG_SEED),
+            (self.bbox.is_some(), FLAG_BBOX),
+            (self.has_species(), FLAG_SPECIES),
+            (self.domain != Domain::Unbounded, FLAG_DOMAIN),
+            (self.anisotropy.is_some(), FLAG_ANISOTROPY),
+            (self.sdf, FLAG_SDF),
+        ] {
+            if set {
+                flags |= flag;
+            }
+        }
+        out.write_all(&flags.to_le_bytes())?;
+        out.write_all(&self.len.unwrap_or(UNKNOWN_LEN).to_le_bytes())?;
+
+        let bbox = self.bbox.clone().unwrap_or(Bbox::new(Vec3::new(0, 0, 0)));
+        write_bbox(out, &bbox)
+    }
+
+    fn read(input: &mut impl Read) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        input.read_exact(&mut magic)?;
+        if magic != MAGIC {
+            return Err(invalid_data("not a DLA dump".to_string()));
+        }
+
+        let version = u16::from_le_bytes(read_array(input)?);
+        if version == 0 {
+            return Err(invalid_data("invalid dump version 0".to_string()));
+        }
+        if version > VERSION {
+            return Err(invalid_data(format!(
+                "unsupported dump version {}, the latest supported one is {}",
+                version, VERSION
+            )));
+        }
+
+        let flags = u16::from_le_bytes(read_array(input)?);
+        if flags & !KNOWN_FLAGS != 0 {
+            return Err(invalid_data(format!("unknown header flags {:#x}", flags)));
+        }
+        let len = u64::from_le_bytes(read_array(input)?);
+        let bbox = read_bbox(input)?;
+        let seed = u64::from_le_bytes(read_array(input)?);
+        let spawn_radius = u32::from_le_bytes(read_array(input)?);
+        let attraction_radius = u16::from_le_bytes(read_array(input)?);
+
+        let [model, connectivity] = read_array(input)?;
+        let growth_model = match (model, connectivity) {
+            (0, _) => GrowthModel::Diffusion,
+            (1, _) => GrowthModel::Ballistic,
+            (2, _) => GrowthModel::Eden,
+            (3, 6) => GrowthModel::Lattice(Connectivity::Six),
+            (3, 18) => GrowthModel::Lattice(Connectivity::Eighteen),
+            (3, 26) => GrowthModel::Lattice(Connectivity::TwentySix),
+            _ => return Err(invalid_data(format!("unknown growth model {}", model))),
+        };
+
+        // every species has a stickiness to each species, more than the
+        // maximum can only come from a corrupted dump
+        let read_count = |input: &mut _| match read_varint(input)? {
+            n if n > MAX_SPECIES as u64 => Err(invalid_data(format!(
+                "{} species are too many, there can be at most {}",
+                n, MAX_SPECIES
+            ))),
+            n => Ok(n),
+        };
+
+        let n = read_count(input)?;
+        let mut species = vec![];
+        for _ in 0..n {
+            let attraction_radius = u16::from_le_bytes(read_array(input)?);
+            let spawn_ratio = f64::from_le_bytes(read_array(input)?);
+            let stickiness = (0..read_count(input)?)
+                .map(|_| Ok(f64::from_le_bytes(read_array(input)?)))
+                .collect::<io::Result<_>>()?;
+
+            species.push(Species {
+                attraction_radius,
+                stickiness,
+                spawn_ratio,
+            });
+        }
+
+        let stickiness = f64::from_le_bytes(read_array(input)?);
+        let anisotropy = if flags & FLAG_ANISOTROPY != 0 {
+            let [symmetry] = read_array(input)?;
+            let symmetry = match symmetry {
+                0 => Symmetry::Cubic,
+                1 => Symmetry::Hexagonal,
+                _ => return Err(invalid_data(format!("unknown symmetry {}", symmetry))),
+            };
+            Some(Anisotropy {
+                symmetry,
+                strength: f64::from_le_bytes(read_array(input)?),
+                hits: u32::from_le_bytes(read_array(input)?),
+            })
+        } else {
+            None
+        };
+        let domain = if flags & FLAG_DOMAIN != 0 {
+            Domain::Periodic(read_bbox(input)?)
+        } else {
+            Domain::Unbounded
+        };
+
+        let obstacles = read_shapes(input)?;
+        let containers = read_shapes(input)?;
+        let attractors = (0..read_varint(input)?)
+            .map(|_| {
+                Ok(Attractor {
+                    shape: read_shape(input)?,
+                    pull: f64::from_le_bytes(read_array(input)?),
+                    stickiness: f64::from_le_bytes(read_array(input)?),
+                    range: f64::from_le_bytes(read_array(input)?),
+                })
+            })
+            .collect::<io::Result<_>>()?;
+        let sinks = read_shapes(input)?;
+
+        Ok(Header {
+            spawn_radius,
+            attraction_radius,
+            growth_model,
+            species,
+            stickiness,
+            anisotropy,
+            domain,
+            obstacles,
+            containers,
+            attractors,
+            sinks,
+            sdf: flags & FLAG_SDF != 0,
+            seed: (flags & FLAG_SEED != 0).then_some(seed),
+            len: (len != UNKNOWN_LEN).then_some(len),
+            bbox: (flags & FLAG_BBOX != 0).then_some(bbox),
+            parents: flags & FLAG_PARENTS != 0,
+        })
+    }
+}
+
+impl<W: Write> Writer<W> {
+    /// Write the header to `out` and return a writer ready to write the
+    /// records.
+    pub fn new(mut out: W, header: Header) -> io::Result<Self> {
+        header.write(&mut out)?;
+
+        Ok(Writer {
+            out,
+            header,
+            prev: Vec3::new(0, 0, 0),
+            written: 0,
+            bbox: None,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// How many records have been written so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn write(&mut self, record: &Record) -> io::Result<()> {
+        let d = Vec3::new(
+            record.cell.x.wrapping_sub(self.prev.x),
+            record.cell.y.wrapping_sub(self.prev.y),
+            record.cell.z.wrapping_sub(self.prev.z),
+        );
+        for c in [d.x, d.y, d.z] {
+            write_varint(&mut self.out, zigzag(c))?;
+        }
+
+        if self.header.has_species() {
+            write_varint(&mut self.out, record.species as u64)?;
+        }
+
+        if self.header.parents {
+            let parent = match record.parent {
+                Some(p) if p < self.written => self.written - p,
+                Some(p) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("record {} cannot have parent {}", self.written, p),
+                    ))
+                }
+                None => 0,
+            };
+            write_varint(&mut self.out, parent)?;
+        }
+
+        self.prev = record.cell;
+        self.written += 1;
+        self.bbox = Some(match &self.bbox {
+            None => Bbox::new(record.cell),
+            Some(b) => b.expand(record.cell),
+        });
+
+        Ok(())
+    }
+
+    /// Flush the records and return the underlying writer. Fail if the
+    /// length in the header doesn't match the records written.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.header.len.is_some_and(|len| len != self.written) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "the header promised {} records, but {} were written",
+                    self.header.len.unwrap_or_default(),
+                    self.written
+                ),
+            ));
+        }
+
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+impl<W: Write + Seek> Writer<W> {
+    /// Like `finish`, but first update the length and the bounding box in
+    /// the header to match the records written. Useful when they're not known
+    /// in advance, for example when writing the cells while the DLA grows.
+    pub fn finish_patched(mut self) -> io::Result<W> {
+        self.header.len = Some(self.written);
+        self.header.bbox = self.bbox.clone();
+
+        let end = self.out.stream_position()?;
+        self.out.seek(SeekFrom::Start(PATCHABLE_START))?;
+        self.header.write_patchable(&mut self.out)?;
+        self.out.seek(SeekFrom::Start(end))?;
+
+        self.finish()
+    }
+}
+
+impl<R: Read> Reader<R> {
+    /// Read the header from `input` and return a reader ready to read the
+    /// records.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let header = Header::read(&mut input)?;
+
+        Ok(Reader {
+            input,
+            header,
+            prev: Vec3::new(0, 0, 0),
+            read: 0,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn read_record(&mut self) -> io::Result<Option<Record>> {
+        if self.header.len.is_some_and(|len| self.read >= len) {
+            return Ok(None);
+        }
+
+        // a clean end of the stream is only allowed between records and when
+        // the length is not known
+        let first = match read_byte(&mut self.input)? {
+            None if self.header.len.is_none() => return Ok(None),
+            None => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Some(b) => b,
+        };
+
+        let dx = unzigzag(read_varint_from(first, &mut self.input)?);
+        let dy = unzigzag(read_varint(&mut self.input)?);
+        let dz = unzigzag(read_varint(&mut self.input)?);
+        let cell = Vec3::new(
+            self.prev.x.wrapping_add(dx),
+            self.prev.y.wrapping_add(dy),
+            self.prev.z.wrapping_add(dz),
+        );
+
+        let species = if self.header.has_species() {
+            read_varint(&mut self.input)? as SpeciesId
+        } else {
+            0
+        };
+
+        let parent = if self.header.parents {
+            match read_varint(&mut self.input)? {
+                0 => None,
+                d if d <= self.read => Some(self.read - d),
+                d => return Err(invalid_data(format!("invalid parent offset {}", d))),
+            }
+        } else {
+            None
+        };
+
+        self.prev = cell;
+        self.read += 1;
+
+        Ok(Some(Record {
+            cell,
+            species,
+            parent,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for Reader<R> {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Save the cells of the DLA as a dump. The octree doesn't remember the order
+/// in which the cells were added, so the records follow its order and have no
+/// parents.
+pub fn save<W: Write>(dla: &Dla, out: W, seed: Option<u64>) -> io::Result<W> {
+    let mut writer = Writer::new(out, Header::of(dla, seed))?;
+    for &cell in dla.cells() {
+        writer.write(&Record {
+            cell,
+            species: dla.species_of(cell),
+            parent: None,
+        })?;
+    }
+
+    writer.finish()
+}
+
+/// Load a DLA from a dump, ready to keep growing with the parameters, the
+/// domain and the shapes found in its header. The parameters and the cells are
+/// validated like those of any other DLA, failing with `InvalidData` if
+/// they're not valid or if the header says some shapes could not be stored.
+pub fn load<R: Read>(input: R) -> io::Result<(Header, Dla)> {
+    let mut reader = Reader::new(input)?;
+    let cells = reader
+        .by_ref()
+        .map(|r| r.map(|r| (r.cell, r.species)))
+        .collect::<io::Result<Vec<_>>>()?;
+    let header = reader.header().clone();
+    if header.sdf {
+        return Err(invalid_data(
+            "the DLA has shapes defined by a signed distance function that cannot be restored"
+                .to_string(),
+        ));
+    }
+
+    let mut builder = DlaBuilder::new()
+        .spawn_radius(header.spawn_radius)
+        .attraction_radius(header.attraction_radius)
+        .growth_model(header.growth_model)
+        .stickiness(header.stickiness)
+        .anisotropy(header.anisotropy.clone())
+        .domain(header.domain.clone());
+    if !header.species.is_empty() {
+        builder = builder.species(header.species.clone());
+    }
+    for o in &header.obstacles {
+        builder = builder.obstacle(o.clone());
+    }
+    for c in &header.containers {
+        builder = builder.container(c.clone());
+    }
+    for a in &header.attractors {
+        builder = builder.attractor(a.clone());
+    }
+    for s in &header.sinks {
+        builder = builder.sink(s.clone());
+    }
+    let dla = Dla::build_with_cells(builder, cells).map_err(|e| invalid_data(e.to_string()))?;
+
+    Ok((header, dla))
+}
+
+fn write_bbox(out: &mut impl Write, bbox: &Bbox) -> io::Result<()> {
+    write_vec3(out, bbox.lower())?;
+    write_vec3(out, bbox.upper())
+}
+
+fn read_bbox(input: &mut impl Read) -> io::Result<Bbox> {
+    let lower = read_vec3(input)?;
+    let upper = read_vec3(input)?;
+    Ok(Bbox::new(lower).expand(upper))
+}
+
+fn write_vec3(out: &mut impl Write, p: Vec3) -> io::Result<()> {
+    for c in [p.x, p.y, p.z] {
+        out.write_all(&c.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+fn read_vec3(input: &mut impl Read) -> io::Result<Vec3> {
+    Ok(Vec3::new(
+        i64::from_le_bytes(read_array(input)?),
+        i64::from_le_bytes(read_array(input)?),
+        i64::from_le_bytes(read_array(input)?),
+    ))
+}
+
+fn write_shapes(out: &mut impl Write, shapes: &[Shape]) -> io::Result<()> {
+    write_varint(out, shapes.len() as u64)?;
+    shapes.iter().try_for_each(|s| write_shape(out, s))
+}
+
+fn read_shapes(input: &mut impl Read) -> io::Result<Vec<Shape>> {
+    (0..read_varint(input)?)
+        .map(|_| read_shape(input))
+        .collect()
+}
+
+/// Write a shape as a tag followed by its parameters, signed distance
+/// functions cannot be written.
+fn write_shape(out: &mut impl Write, shape: &Shape) -> io::Result<()> {
+    match shape {
+        Shape::Box(bbox) => {
+            out.write_all(&[0])?;
+            write_bbox(out, bbox)
+        }
+        Shape::Sphere { center, radius } => {
+            out.write_all(&[1])?;
+            write_vec3(out, *center)?;
+            out.write_all(&radius.to_le_bytes())
+        }
+        Shape::Cylinder {
+            base,
+            axis,
+            radius,
+            height,
+        } => {
+            let axis: u8 = match axis {
+                Axis::X => 0,
+                Axis::Y => 1,
+                Axis::Z => 2,
+            };
+            out.write_all(&[2, axis])?;
+            write_vec3(out, *base)?;
+            out.write_all(&radius.to_le_bytes())?;
+            out.write_all(&height.to_le_bytes())
+        }
+        Shape::Mesh(mesh) => {
+            out.write_all(&[3])?;
+            write_varint(out, mesh.triangles().len() as u64)?;
+            for p in mesh.triangles().iter().flatten() {
+                for c in [p.x, p.y, p.z] {
+                    out.write_all(&c.to_le_bytes())?;
+                }
+            }
+            Ok(())
+        }
+        Shape::Sdf(_) => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "shapes defined by a signed distance function cannot be saved",
+        )),
+    }
+}
+
+fn read_shape(input: &mut impl Read) -> io::Result<Shape> {
+    let [tag] = read_array(input)?;
+    match tag {
+        0 => Ok(Shape::Box(read_bbox(input)?)),
+        1 => Ok(Shape::Sphere {
+            center: read_vec3(input)?,
+            radius: i64::from_le_bytes(read_array(input)?),
+        }),
+        2 => {
+            let [axis] = read_array(input)?;
+            let axis = match axis {
+                0 => Axis::X,
+                1 => Axis::Y,
+                2 => Axis::Z,
+                _ => return Err(invalid_data(format!("unknown axis {}", axis))),
+            };
+            Ok(Shape::Cylinder {
+                base: read_vec3(input)?,
+                axis,
+                radius: i64::from_le_bytes(read_array(input)?),
+                height: i64::from_le_bytes(read_array(input)?),
+            })
+        }
+        3 => {
+            let n = read_varint(input)?;
+            let mut read_point = || -> io::Result<Vec3f> {
+                Ok(Vec3f::new(
+                    f64::from_le_bytes(read_array(input)?),
+                    f64::from_le_bytes(read_array(input)?),
+                    f64::from_le_bytes(read_array(input)?),
+                ))
+            };
+            let triangles = (0..n)
+                .map(|_| Ok([read_point()?, read_point()?, read_point()?]))
+                .collect::<io::Result<Vec<_>>>()?;
+
+            Mesh::new(triangles)
+                .map(Shape::Mesh)
+                .ok_or_else(|| invalid_data("empty mesh".to_string()))
+        }
+        _ => Err(invalid_data(format!("unknown shape {}", tag))),
+    }
+}
+
+fn invalid_data(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+fn unzigzag(v: u64) -> i64 {
+    ((v >> 1) as i64) ^ -((v & 1) as i64)
+}
+
+fn write_varint(out: &mut impl Write, mut v: u64) -> io::Result<()> {
+    let mut buf = [0; 10];
+    let mut n = 0;
+    loop {
+        let b = (v & 0x7f) as u8;
+        v >>= 7;
+        if v == 0 {
+            buf[n] = b;
+            n += 1;
+            break;
+        }
+        buf[n] = b | 0x80;
+        n += 1;
+    }
+
+    out.write_all(&buf[..n])
+}
+
+fn read_varint(input: &mut impl Read) -> io::Result<u64> {
+    let [first] = read_array(input)?;
+    read_varint_from(first, input)
+}
+
+fn read_varint_from(first: u8, input: &mut impl Read) -> io::Result<u64> {
+    let mut v = u64::from(first & 0x7f);
+    let mut b = first;
+    let mut shift = 7;
+    while b & 0x80 != 0 {
+        if shift >= 64 {
+            return Err(invalid_data("varint too long".to_string()));
+        }
+
+        [b] = read_array(input)?;
+        v |= u64::from(b & 0x7f) << shift;
+        shift += 7;
+    }
+
+    Ok(v)
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut b = [0];
+    loop {
+        match input.read(&mut b) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(b[0])),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+fn read_array<const N: usize>(input: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut buf = [0; N];
+    input.read_exact(&mut buf)?;
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::geo::MAX_COORD;
+    use crate::shape::Sdf;
+    use crate::Event;
+
+    #[test]
+    fn streamed_dump_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.set_species(vec![Species::new(8), Species::new(6)]);
+
+        let header = Header {
+            len: None,
+            bbox: None,
+            parents: true,
+            ..Header::of(&dla, Some(42))
+        };
+        let mut writer = Writer::new(Cursor::new(vec![]), header).unwrap();
+        writer
+            .write(&Record {
+                cell: Vec3::new(0, 0, 0),
+                species: 0,
+                parent: None,
+            })
+            .unwrap();
+
+        let mut order = vec![Vec3::new(0, 0, 0)];
+        for _ in 0..200 {
+            dla.add_with(&mut rng, &mut |e: &Event| {
+                if let Event::Attached {
+                    cell,
+                    parent,
+                    species,
+                } = e
+                {
+                    let parent = order.iter().position(|c| c == parent).unwrap();
+                    order.push(*cell);
+                    writer
+                        .write(&Record {
+                            cell: *cell,
+                            species: *species,
+                            parent: Some(parent as u64),
+                        })
+                        .unwrap();
+                }
+            })
+            .unwrap();
+        }
+        let bytes = writer.finish_patched().unwrap().into_inner();
+
+        let mut reader = Reader::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(reader.header().len, Some(order.len() as u64));
+        assert_eq!(reader.header().bbox, Some(dla.bbox()));
+        assert_eq!(reader.header().seed, Some(42));
+
+        let records = reader.by_ref().collect::<io::Result<Vec<_>>>().unwrap();
+        assert_eq!(records.iter().map(|r| r.cell).collect::<Vec<_>>(), order);
+        for r in &records[1..] {
+            let parent = records[r.parent.unwrap() as usize].cell;
+            assert!(r.cell.dist2(parent) <= 8 * 8);
+            assert_eq!(r.species, dla.species_of(r.cell));
+        }
+
+        let (header, loaded) = load(Cursor::new(&bytes)).unwrap();
+        assert_eq!(header.species, dla.species());
+        assert_eq!(loaded.octree(), dla.octree());
+        assert_eq!(loaded.species_of(records[10].cell), records[10].species);
+
+        // without the patched length the records are read until the end
+        let saved = save(&loaded, vec![], None).unwrap();
+        let (_, reloaded) = load(Cursor::new(&saved)).unwrap();
+        assert_eq!(reloaded.octree(), dla.octree());
+        assert!(saved.len() < dla.len() * 8);
+    }
+
+    #[test]
+    fn corrupted_dumps_are_rejected() {
+        let dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0), Vec3::new(1, 0, 0)]).unwrap();
+        let header = Header {
+            len: None,
+            bbox: None,
+            ..Header::of(&dla, None)
+        };
+
+        // deltas between extreme coordinates wrap around
+        let cells = [
+            Vec3::new(i64::MIN, i64::MAX, 0),
+            Vec3::new(i64::MAX, i64::MIN, -1),
+            Vec3::new(0, 0, 0),
+        ];
+        let mut writer = Writer::new(vec![], header.clone()).unwrap();
+        for cell in cells {
+            writer
+                .write(&Record {
+                    cell,
+                    species: 0,
+                    parent: None,
+                })
+                .unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        let read = Reader::new(Cursor::new(&bytes))
+            .unwrap()
+            .map(|r| r.map(|r| r.cell))
+            .collect::<io::Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, cells);
+
+        let bytes = save(&dla, vec![], None).unwrap();
+        assert!(load(Cursor::new(&bytes[..bytes.len() - 1])).is_err());
+        assert!(Reader::new(Cursor::new(&b"PLY\n"[..])).is_err());
+
+        let mut future = bytes.clone();
+        future[4] = 2;
+        let err = Reader::new(Cursor::new(&future)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut writer = Writer::new(vec![], Header::of(&dla, None)).unwrap();
+        writer
+            .write(&Record {
+                cell: Vec3::new(0, 0, 0),
+                species: 0,
+                parent: None,
+            })
+            .unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn invalid_dumps_are_rejected() {
+        let dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        let header = Header {
+            len: None,
+            bbox: None,
+            ..Header::of(&dla, None)
+        };
+        let dump = |header: &Header, cells: &[(Vec3, SpeciesId)]| {
+            let mut writer = Writer::new(vec![], header.clone()).unwrap();
+            for &(cell, species) in cells {
+                writer
+                    .write(&Record {
+                        cell,
+                        species,
+                        parent: None,
+                    })
+                    .unwrap();
+            }
+            writer.finish().unwrap()
+        };
+        let origin = Vec3::new(0, 0, 0);
+        let far = Vec3::new(0, MAX_COORD + 1, 0);
+        let two_species = Header {
+            species: vec![Species::new(8); 2],
+            ..header.clone()
+        };
+
+        for (header, cells, error) in [
+            (&header, &[][..], "at least one seed"),
+            (&header, &[(origin, 0), (far, 0)], "too far from the origin"),
+            (
+                &two_species,
+                &[(origin, 0), (far, 1)],
+                "too far from the origin",
+            ),
+            (
+                &two_species,
+                &[(origin, 1), (Vec3::new(1, 0, 0), 2)],
+                "unknown species 2",
+            ),
+            (
+                &Header {
+                    attraction_radius: 3,
+                    ..header.clone()
+                },
+                &[(origin, 0)],
+                "attraction radius",
+            ),
+            (
+                &Header {
+                    species: vec![Species::new(8); MAX_SPECIES + 1],
+                    ..header.clone()
+                },
+                &[(origin, 0)],
+                "too many",
+            ),
+        ] {
+            let err = load(Cursor::new(dump(header, cells))).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(
+                err.to_string().contains(error),
+                "`{}` doesn't mention `{}`",
+                err,
+                error
+            );
+        }
+
+        // the lattice models don't care about the attraction radius
+        let lattice = Header {
+            attraction_radius: 1,
+            growth_model: GrowthModel::Lattice(Connectivity::Six),
+            ..header.clone()
+        };
+        let (_, loaded) = load(Cursor::new(dump(&lattice, &[(origin, 0)]))).unwrap();
+        assert_eq!(loaded.len(), 1);
+
+        let mut unversioned = dump(&header, &[(origin, 0)]);
+        unversioned[4] = 0;
+        let err = load(Cursor::new(unversioned)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "invalid dump version 0");
+    }
+
+    #[test]
+    fn configuration_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let tetrahedron = Mesh::new(vec![
+            [
+                Vec3f::new(40.0, 0.0, 0.0),
+                Vec3f::new(44.0, 0.0, 0.0),
+                Vec3f::new(40.0, 4.0, 0.0),
+            ],
+            [
+                Vec3f::new(40.0, 0.0, 0.0),
+                Vec3f::new(40.0, 4.0, 0.0),
+                Vec3f::new(40.0, 0.0, 4.0),
+            ],
+        ])
+        .unwrap();
+        let mut dla = DlaBuilder::new()
+            .seeds(vec![Vec3::new(0, 0, 0)])
+            .stickiness(0.5)
+            .anisotropy(Some(Anisotropy {
+                symmetry: Symmetry::Hexagonal,
+                strength: 0.7,
+                hits: 3,
+            }))
+            .domain(Domain::periodic(Vec3::new(-50, -50, -50), 100).unwrap())
+            .obstacle(Shape::Box(
+                Bbox::new(Vec3::new(20, 20, 20)).expand(Vec3::new(25, 25, 25)),
+            ))
+            .container(Shape::Sphere {
+                center: Vec3::new(0, 0, 0),
+                radius: 45,
+            })
+            .attractor(Attractor {
+                shape: Shape::Cylinder {
+                    base: Vec3::new(0, -30, 0),
+                    axis: Axis::Y,
+                    radius: 3,
+                    height: 5,
+                },
+                pull: 0.3,
+                stickiness: 0.9,
+                range: 4.0,
+            })
+            .sink(Shape::Mesh(tetrahedron))
+            .build()
+            .unwrap();
+        for _ in 0..20 {
+            dla.add(&mut rng);
+        }
+
+        let bytes = save(&dla, vec![], Some(42)).unwrap();
+        let (header, mut loaded) = load(Cursor::new(&bytes)).unwrap();
+        assert!(!header.sdf);
+
+        assert_eq!(loaded.octree(), dla.octree());
+        assert_eq!(loaded.domain(), dla.domain());
+        assert_eq!(loaded.stickiness(), 0.5);
+        assert_eq!(loaded.anisotropy(), dla.anisotropy());
+        for (restored, original) in [
+            (loaded.obstacles(), dla.obstacles()),
+            (loaded.containers(), dla.containers()),
+            (loaded.sinks(), dla.sinks()),
+        ] {
+            assert_eq!(format!("{:?}", restored), format!("{:?}", original));
+        }
+        assert_eq!(
+            format!("{:?}", loaded.attractors()),
+            format!("{:?}", dla.attractors())
+        );
+
+        // the restored DLA keeps growing in its periodic box
+        for _ in 0..20 {
+            let c = loaded.add(&mut rng);
+            assert_eq!(loaded.domain().wrap(c), c);
+        }
+    }
+
+    #[test]
+    fn unrestorable_dumps_are_rejected() {
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.add_obstacle(Shape::Sdf(Sdf::new(|p| p.x as f64 - 20.0)));
+
+        let bytes = save(&dla, vec![], None).unwrap();
+        let reader = Reader::new(Cursor::new(&bytes)).unwrap();
+        assert!(reader.header().sdf);
+        assert!(reader.header().obstacles.is_empty());
+
+        let err = load(Cursor::new(&bytes)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("signed distance function"));
+
+        // flags added by future versions are rejected too
+        let mut bytes = save(
+            &Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap(),
+            vec![],
+            None,
+        )
+        .unwrap();
+        bytes[7] |= 0x80;
+        let err = load(Cursor::new(&bytes)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().contains("unknown header flags"));
+    }
+}
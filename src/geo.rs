@@ -1,4 +1,10 @@
+/// Largest absolute value a coordinate of a `Vec3` can safely have: the
+/// squared distance between any two points within this bound fits in an
+/// `i64`.
+pub const MAX_COORD: i64 = 1 << 29;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vec3 {
     pub x: i64,
     pub y: i64,
@@ -18,14 +24,81 @@ impl Vec3 {
         Vec3::new(self.x.max(o.x), self.y.max(o.y), self.z.max(o.z))
     }
 
+    pub fn dot(&self, o: Vec3) -> i64 {
+        self.x * o.x + self.y * o.y + self.z * o.z
+    }
+
+    pub fn cross(&self, o: Vec3) -> Self {
+        Vec3::new(
+            self.y * o.z - self.z * o.y,
+            self.z * o.x - self.x * o.z,
+            self.x * o.y - self.y * o.x,
+        )
+    }
+
+    pub fn abs(&self) -> Self {
+        Vec3::new(self.x.abs(), self.y.abs(), self.z.abs())
+    }
+
     pub fn dist2(&self, o: Vec3) -> i64 {
         (o - *self).norm2()
     }
 
+    /// Squared norm, it doesn't overflow as long as the coordinates are within
+    /// `MAX_COORD`, see `checked_norm2` and `norm2_wide` otherwise.
     pub fn norm2(&self) -> i64 {
         self.x.pow(2) + self.y.pow(2) + self.z.pow(2)
     }
 
+    /// Squared norm, or `None` if it doesn't fit in an `i64`.
+    pub fn checked_norm2(&self) -> Option<i64> {
+        self.x
+            .checked_mul(self.x)?
+            .checked_add(self.y.checked_mul(self.y)?)?
+            .checked_add(self.z.checked_mul(self.z)?)
+    }
+
+    /// Squared distance, or `None` if it doesn't fit in an `i64`.
+    pub fn checked_dist2(&self, o: Vec3) -> Option<i64> {
+        o.checked_sub(*self)?.checked_norm2()
+    }
+
+    /// Squared norm computed with `u128`s, it never overflows.
+    pub fn norm2_wide(&self) -> u128 {
+        let sq = |c: i64| u128::from(c.unsigned_abs()).pow(2);
+        sq(self.x) + sq(self.y) + sq(self.z)
+    }
+
+    /// Squared distance computed with `u128`s, it only saturates when the
+    /// points are at opposite ends of the `i64` range.
+    pub fn dist2_wide(&self, o: Vec3) -> u128 {
+        let sq = |a: i64, b: i64| u128::from(a.abs_diff(b)).pow(2);
+        sq(self.x, o.x)
+            .saturating_add(sq(self.y, o.y))
+            .saturating_add(sq(self.z, o.z))
+    }
+
+    pub fn checked_add(&self, o: Vec3) -> Option<Vec3> {
+        Some(Vec3::new(
+            self.x.checked_add(o.x)?,
+            self.y.checked_add(o.y)?,
+            self.z.checked_add(o.z)?,
+        ))
+    }
+
+    pub fn checked_sub(&self, o: Vec3) -> Option<Vec3> {
+        Some(Vec3::new(
+            self.x.checked_sub(o.x)?,
+            self.y.checked_sub(o.y)?,
+            self.z.checked_sub(o.z)?,
+        ))
+    }
+
+    /// Whether all the coordinates are within `MAX_COORD`.
+    pub fn is_bounded(&self) -> bool {
+        Bbox::bounds().contains(*self)
+    }
+
     /// WARNING: not exact, rounding issues
     pub fn norm(&self) -> i64 {
         (self.norm2() as f64).sqrt() as i64
@@ -38,7 +111,148 @@ impl Vec3 {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vec3f {
+    pub x: f64,
+    pub y: f64,
+    pub z: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+}
+
+/// An affine transform of the space, stored as the first three rows of a 4x4
+/// matrix since the last one is always `0 0 0 1`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Transform {
+    m: [[f64; 4]; 3],
+}
+
+/// A sphere with a floating point center and radius.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sphere {
+    pub center: Vec3f,
+    pub radius: f64,
+}
+
+/// A half line starting from `origin` and going along `direction`, which is
+/// not required to be a unit vector.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Ray {
+    pub origin: Vec3f,
+    pub direction: Vec3f,
+}
+
+impl Axis {
+    pub const ALL: [Axis; 3] = [Axis::X, Axis::Y, Axis::Z];
+
+    /// The unit vector pointing along the axis.
+    pub fn unit(self) -> Vec3 {
+        match self {
+            Axis::X => Vec3::new(1, 0, 0),
+            Axis::Y => Vec3::new(0, 1, 0),
+            Axis::Z => Vec3::new(0, 0, 1),
+        }
+    }
+}
+
+impl Vec3f {
+    pub const fn new(x: f64, y: f64, z: f64) -> Self {
+        Vec3f { x, y, z }
+    }
+
+    pub fn min(&self, o: Vec3f) -> Self {
+        Vec3f::new(self.x.min(o.x), self.y.min(o.y), self.z.min(o.z))
+    }
+
+    pub fn max(&self, o: Vec3f) -> Self {
+        Vec3f::new(self.x.max(o.x), self.y.max(o.y), self.z.max(o.z))
+    }
+
+    pub fn abs(&self) -> Self {
+        Vec3f::new(self.x.abs(), self.y.abs(), self.z.abs())
+    }
+
+    pub fn signum(&self) -> Self {
+        Vec3f::new(self.x.signum(), self.y.signum(), self.z.signum())
+    }
+
+    pub fn dot(&self, o: Vec3f) -> f64 {
+        self.x * o.x + self.y * o.y + self.z * o.z
+    }
+
+    pub fn cross(&self, o: Vec3f) -> Self {
+        Vec3f::new(
+            self.y * o.z - self.z * o.y,
+            self.z * o.x - self.x * o.z,
+            self.x * o.y - self.y * o.x,
+        )
+    }
+
+    pub fn dist2(&self, o: Vec3f) -> f64 {
+        (o - *self).norm2()
+    }
+
+    pub fn dist(&self, o: Vec3f) -> f64 {
+        (o - *self).norm()
+    }
+
+    pub fn norm2(&self) -> f64 {
+        self.dot(*self)
+    }
+
+    pub fn norm(&self) -> f64 {
+        self.norm2().sqrt()
+    }
+
+    pub fn normalized(&self) -> Self {
+        *self / self.norm()
+    }
+
+    /// Whether all the coordinates are neither infinite nor NaN.
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
+
+    /// Round each coordinate towards negative infinity.
+    pub fn floor(&self) -> Vec3 {
+        Vec3::new(
+            self.x.floor() as i64,
+            self.y.floor() as i64,
+            self.z.floor() as i64,
+        )
+    }
+
+    /// Round each coordinate towards positive infinity.
+    pub fn ceil(&self) -> Vec3 {
+        Vec3::new(
+            self.x.ceil() as i64,
+            self.y.ceil() as i64,
+            self.z.ceil() as i64,
+        )
+    }
+
+    /// Round each coordinate to the closest integer.
+    pub fn round(&self) -> Vec3 {
+        Vec3::new(
+            self.x.round() as i64,
+            self.y.round() as i64,
+            self.z.round() as i64,
+        )
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bbox {
     lower: Vec3,
     upper: Vec3,
@@ -74,9 +288,29 @@ impl Bbox {
             && (self.lower.z..=self.upper.z).contains(&p.z)
     }
 
+    /// The region of space where coordinates are within `MAX_COORD`.
+    pub const fn bounds() -> Self {
+        Bbox {
+            lower: Vec3::new(-MAX_COORD, -MAX_COORD, -MAX_COORD),
+            upper: Vec3::new(MAX_COORD, MAX_COORD, MAX_COORD),
+        }
+    }
+
+    /// Volume of the box, saturating at `i64::MAX` for huge boxes.
     pub fn volume(&self) -> i64 {
-        let d = self.upper - self.lower;
-        d.x * d.y * d.z
+        self.checked_volume().unwrap_or(i64::MAX)
+    }
+
+    /// Volume of the box, or `None` if it doesn't fit in an `i64`.
+    pub fn checked_volume(&self) -> Option<i64> {
+        let d = self.upper.checked_sub(self.lower)?;
+        d.x.checked_mul(d.y)?.checked_mul(d.z)
+    }
+
+    /// The part of the box inside `bounds`, it's the box clamped to a corner
+    /// of `bounds` when they don't overlap.
+    pub fn clamped(&self, bounds: &Bbox) -> Bbox {
+        Bbox::new(bounds.clamp(self.lower)).expand(bounds.clamp(self.upper))
     }
 
     pub fn dimensions(&self) -> Vec3 {
@@ -104,6 +338,273 @@ impl Bbox {
             upper: self.upper.max(b.upper),
         }
     }
+
+    /// The box shared by both boxes, if they overlap.
+    pub fn intersection(&self, b: &Bbox) -> Option<Self> {
+        let lower = self.lower.max(b.lower);
+        let upper = self.upper.min(b.upper);
+
+        (lower.x <= upper.x && lower.y <= upper.y && lower.z <= upper.z)
+            .then_some(Bbox { lower, upper })
+    }
+
+    /// Whether the boxes share at least a point, touching boxes overlap.
+    pub fn overlaps(&self, b: &Bbox) -> bool {
+        self.intersection(b).is_some()
+    }
+
+    pub fn corners(&self) -> [Vec3; 8] {
+        let (l, u) = (self.lower, self.upper);
+        [
+            Vec3::new(l.x, l.y, l.z),
+            Vec3::new(u.x, l.y, l.z),
+            Vec3::new(l.x, u.y, l.z),
+            Vec3::new(u.x, u.y, l.z),
+            Vec3::new(l.x, l.y, u.z),
+            Vec3::new(u.x, l.y, u.z),
+            Vec3::new(l.x, u.y, u.z),
+            Vec3::new(u.x, u.y, u.z),
+        ]
+    }
+}
+
+impl Transform {
+    pub const fn identity() -> Self {
+        Transform {
+            m: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 1.0, 0.0],
+            ],
+        }
+    }
+
+    pub fn translate(d: Vec3f) -> Self {
+        Transform {
+            m: [
+                [1.0, 0.0, 0.0, d.x],
+                [0.0, 1.0, 0.0, d.y],
+                [0.0, 0.0, 1.0, d.z],
+            ],
+        }
+    }
+
+    /// Scale each axis by the corresponding coordinate of `s`, negative
+    /// factors mirror the space.
+    pub fn scale(s: Vec3f) -> Self {
+        Transform {
+            m: [
+                [s.x, 0.0, 0.0, 0.0],
+                [0.0, s.y, 0.0, 0.0],
+                [0.0, 0.0, s.z, 0.0],
+            ],
+        }
+    }
+
+    /// Counterclockwise rotation of `angle` radians around `axis`, which
+    /// doesn't need to be a unit vector.
+    pub fn rotate(axis: Vec3f, angle: f64) -> Self {
+        let Vec3f { x, y, z } = axis.normalized();
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+
+        Transform {
+            m: [
+                [t * x * x + c, t * x * y - s * z, t * x * z + s * y, 0.0],
+                [t * x * y + s * z, t * y * y + c, t * y * z - s * x, 0.0],
+                [t * x * z - s * y, t * y * z + s * x, t * z * z + c, 0.0],
+            ],
+        }
+    }
+
+    /// The transform that applies `self` first and then `o`.
+    pub fn then(&self, o: &Transform) -> Self {
+        let mut m = [[0.0; 4]; 3];
+        for (r, row) in m.iter_mut().enumerate() {
+            for (c, v) in row.iter_mut().enumerate() {
+                *v = (0..3).map(|k| o.m[r][k] * self.m[k][c]).sum::<f64>();
+            }
+            row[3] += o.m[r][3];
+        }
+
+        Transform { m }
+    }
+
+    pub fn apply(&self, p: Vec3f) -> Vec3f {
+        self.apply_vector(p) + Vec3f::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    /// Transform a direction, translations don't affect it.
+    pub fn apply_vector(&self, v: Vec3f) -> Vec3f {
+        let row = |r: &[f64; 4]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+        Vec3f::new(row(&self.m[0]), row(&self.m[1]), row(&self.m[2]))
+    }
+
+    /// Transform a lattice point and snap it back to the closest lattice
+    /// point.
+    pub fn apply_lattice(&self, p: Vec3) -> Vec3 {
+        self.apply(Vec3f::from(p)).round()
+    }
+
+    /// The smallest box containing the transformed box.
+    pub fn apply_bbox(&self, b: &Bbox) -> Bbox {
+        let corners = b.corners().map(|c| self.apply(Vec3f::from(c)));
+        let lower = corners.iter().fold(corners[0], |l, c| l.min(*c));
+        let upper = corners.iter().fold(corners[0], |u, c| u.max(*c));
+
+        // rotations by multiples of 90° leave tiny errors around integers that
+        // would otherwise grow the box by one along some axes
+        let snap = |v: Vec3f| {
+            let r = Vec3f::from(v.round());
+            let s = |v: f64, r: f64| if (v - r).abs() < 1e-9 { r } else { v };
+            Vec3f::new(s(v.x, r.x), s(v.y, r.y), s(v.z, r.z))
+        };
+
+        Bbox::new(snap(lower).floor()).expand(snap(upper).ceil())
+    }
+
+    /// The transform that undoes this one, if it's not degenerate.
+    pub fn inverse(&self) -> Option<Self> {
+        let a = &self.m;
+        let cofactor = |r0: usize, r1: usize, c0: usize, c1: usize| {
+            a[r0][c0] * a[r1][c1] - a[r0][c1] * a[r1][c0]
+        };
+
+        let det = a[0][0] * cofactor(1, 2, 1, 2) - a[0][1] * cofactor(1, 2, 0, 2)
+            + a[0][2] * cofactor(1, 2, 0, 1);
+        if det.abs() < f64::EPSILON {
+            return None;
+        }
+
+        // the inverse of the linear part is its adjugate divided by the
+        // determinant
+        let lin = [
+            [
+                cofactor(1, 2, 1, 2),
+                -cofactor(0, 2, 1, 2),
+                cofactor(0, 1, 1, 2),
+            ],
+            [
+                -cofactor(1, 2, 0, 2),
+                cofactor(0, 2, 0, 2),
+                -cofactor(0, 1, 0, 2),
+            ],
+            [
+                cofactor(1, 2, 0, 1),
+                -cofactor(0, 2, 0, 1),
+                cofactor(0, 1, 0, 1),
+            ],
+        ];
+
+        let mut m = [[0.0; 4]; 3];
+        for r in 0..3 {
+            for c in 0..3 {
+                m[r][c] = lin[r][c] / det;
+            }
+            m[r][3] = -(0..3).map(|k| m[r][k] * a[k][3]).sum::<f64>();
+        }
+
+        Some(Transform { m })
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl Sphere {
+    pub const fn new(center: Vec3f, radius: f64) -> Self {
+        Sphere { center, radius }
+    }
+
+    /// The smallest sphere centered in the center of the box that contains
+    /// it.
+    pub fn bounding(b: &Bbox) -> Self {
+        let lower = Vec3f::from(b.lower());
+        let upper = Vec3f::from(b.upper());
+
+        Sphere::new((lower + upper) / 2.0, lower.dist(upper) / 2.0)
+    }
+
+    pub fn contains(&self, p: Vec3f) -> bool {
+        p.dist2(self.center) <= self.radius.powi(2)
+    }
+
+    /// The smallest lattice box containing the sphere.
+    pub fn bbox(&self) -> Bbox {
+        let r = Vec3f::new(self.radius, self.radius, self.radius);
+        Bbox::new((self.center - r).floor()).expand((self.center + r).ceil())
+    }
+}
+
+impl Ray {
+    pub const fn new(origin: Vec3f, direction: Vec3f) -> Self {
+        Ray { origin, direction }
+    }
+
+    /// The point at `t` times the direction away from the origin.
+    pub fn at(&self, t: f64) -> Vec3f {
+        self.origin + self.direction * t
+    }
+
+    /// The smallest non negative `t` at which the ray is on the surface of
+    /// the sphere, 0 if the origin is inside it.
+    pub fn intersect_sphere(&self, sphere: &Sphere) -> Option<f64> {
+        // solve |origin + t * direction - center| = radius
+        let o = self.origin - sphere.center;
+        let a = self.direction.norm2();
+        let b = self.direction.dot(o);
+        let c = o.norm2() - sphere.radius.powi(2);
+        if c <= 0.0 {
+            return Some(0.0);
+        }
+
+        let disc = b * b - a * c;
+        if disc < 0.0 || a == 0.0 {
+            return None;
+        }
+
+        let t = (-b - disc.sqrt()) / a;
+        (t >= 0.0).then_some(t)
+    }
+
+    /// The range of `t` in which the ray is inside the box, clipped to non
+    /// negative values, if the ray hits the box at all.
+    pub fn intersect_bbox(&self, b: &Bbox) -> Option<(f64, f64)> {
+        let lower = Vec3f::from(b.lower());
+        let upper = Vec3f::from(b.upper());
+
+        let mut range = (0.0_f64, f64::INFINITY);
+        for axis in Axis::ALL {
+            let (o, d) = (self.origin[axis], self.direction[axis]);
+            if d == 0.0 {
+                if o < lower[axis] || o > upper[axis] {
+                    return None;
+                }
+                continue;
+            }
+
+            let t0 = (lower[axis] - o) / d;
+            let t1 = (upper[axis] - o) / d;
+            range = (range.0.max(t0.min(t1)), range.1.min(t0.max(t1)));
+        }
+
+        (range.0 <= range.1).then_some(range)
+    }
+}
+
+impl std::ops::Index<Axis> for Vec3 {
+    type Output = i64;
+
+    fn index(&self, axis: Axis) -> &i64 {
+        match axis {
+            Axis::X => &self.x,
+            Axis::Y => &self.y,
+            Axis::Z => &self.z,
+        }
+    }
 }
 
 impl std::ops::Add for Vec3 {
@@ -177,3 +678,199 @@ impl std::ops::Neg for Vec3 {
         Vec3::new(-self.x, -self.y, -self.z)
     }
 }
+
+impl From<Vec3> for Vec3f {
+    fn from(v: Vec3) -> Self {
+        Vec3f::new(v.x as f64, v.y as f64, v.z as f64)
+    }
+}
+
+impl From<[i64; 3]> for Vec3 {
+    fn from([x, y, z]: [i64; 3]) -> Self {
+        Vec3::new(x, y, z)
+    }
+}
+
+impl From<Vec3> for [i64; 3] {
+    fn from(v: Vec3) -> Self {
+        [v.x, v.y, v.z]
+    }
+}
+
+impl From<[f64; 3]> for Vec3f {
+    fn from([x, y, z]: [f64; 3]) -> Self {
+        Vec3f::new(x, y, z)
+    }
+}
+
+impl From<Vec3f> for [f64; 3] {
+    fn from(v: Vec3f) -> Self {
+        [v.x, v.y, v.z]
+    }
+}
+
+impl std::ops::Index<Axis> for Vec3f {
+    type Output = f64;
+
+    fn index(&self, axis: Axis) -> &f64 {
+        match axis {
+            Axis::X => &self.x,
+            Axis::Y => &self.y,
+            Axis::Z => &self.z,
+        }
+    }
+}
+
+impl std::ops::Add for Vec3f {
+    type Output = Self;
+
+    fn add(self, d: Vec3f) -> Self::Output {
+        Vec3f::new(self.x + d.x, self.y + d.y, self.z + d.z)
+    }
+}
+
+impl std::ops::Sub for Vec3f {
+    type Output = Self;
+
+    fn sub(self, d: Vec3f) -> Self::Output {
+        Vec3f::new(self.x - d.x, self.y - d.y, self.z - d.z)
+    }
+}
+
+impl std::ops::Mul<f64> for Vec3f {
+    type Output = Self;
+
+    fn mul(self, d: f64) -> Self::Output {
+        Vec3f::new(self.x * d, self.y * d, self.z * d)
+    }
+}
+
+impl std::ops::Div<f64> for Vec3f {
+    type Output = Self;
+
+    fn div(self, d: f64) -> Self::Output {
+        Vec3f::new(self.x / d, self.y / d, self.z / d)
+    }
+}
+
+impl std::ops::Neg for Vec3f {
+    type Output = Self;
+
+    fn neg(self) -> Vec3f {
+        Vec3f::new(-self.x, -self.y, -self.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extreme_coordinates_do_not_overflow() {
+        let max = Vec3::new(MAX_COORD, MAX_COORD, MAX_COORD);
+        assert!(max.is_bounded());
+        assert!(!(max + 1).is_bounded());
+        assert_eq!(max.dist2(max * -1), 3 * (2 * MAX_COORD).pow(2));
+        assert_eq!(max.checked_dist2(max * -1), Some(max.dist2(max * -1)));
+
+        let huge = Vec3::new(i64::MAX, i64::MIN, 0);
+        assert_eq!(huge.checked_norm2(), None);
+        assert_eq!(huge.checked_dist2(Vec3::new(0, 0, 0)), None);
+        assert_eq!(huge.checked_add(Vec3::new(1, 0, 0)), None);
+        assert_eq!(
+            huge.norm2_wide(),
+            u128::from(i64::MAX as u64).pow(2) + u128::from(i64::MIN.unsigned_abs()).pow(2)
+        );
+        assert_eq!(
+            Vec3::new(i64::MIN, 0, 0).dist2_wide(Vec3::new(i64::MAX, 0, 0)),
+            u128::from(u64::MAX).pow(2)
+        );
+        assert_eq!(
+            Vec3::new(i64::MIN, i64::MIN, 0).dist2_wide(Vec3::new(i64::MAX, i64::MAX, 0)),
+            u128::MAX
+        );
+
+        let bbox = Bbox::new(huge).expand(Vec3::new(0, 0, 0));
+        assert_eq!(bbox.checked_volume(), None);
+        assert_eq!(bbox.volume(), i64::MAX);
+        assert_eq!(
+            bbox.clamped(&Bbox::bounds()),
+            Bbox::new(Vec3::new(0, -MAX_COORD, 0)).expand(Vec3::new(MAX_COORD, 0, 0))
+        );
+    }
+
+    #[test]
+    fn transforms_compose_and_invert() {
+        let p = Vec3f::new(1.0, 2.0, 3.0);
+
+        let rot = Transform::rotate(Axis::Z.unit().into(), std::f64::consts::FRAC_PI_2);
+        assert_eq!(rot.apply_lattice(Vec3::new(1, 2, 3)), Vec3::new(-2, 1, 3));
+        assert_eq!(
+            rot.apply_vector(Vec3f::new(1.0, 0.0, 0.0)).round(),
+            Vec3::new(0, 1, 0)
+        );
+
+        let t = Transform::scale(Vec3f::new(2.0, -1.0, 1.0))
+            .then(&rot)
+            .then(&Transform::translate(Vec3f::new(10.0, 0.0, -5.0)));
+        assert_eq!(t.apply(p).round(), Vec3::new(12, 2, -2));
+
+        let inv = t.inverse().unwrap();
+        assert!(inv.apply(t.apply(p)).dist(p) < 1e-9);
+        assert!(t.then(&inv).apply(p).dist(p) < 1e-9);
+        assert_eq!(Transform::scale(Vec3f::new(1.0, 0.0, 1.0)).inverse(), None);
+
+        let b = Bbox::new(Vec3::new(0, 0, 0)).expand(Vec3::new(2, 1, 1));
+        assert_eq!(
+            rot.apply_bbox(&b),
+            Bbox::new(Vec3::new(-1, 0, 0)).expand(Vec3::new(0, 2, 1))
+        );
+    }
+
+    #[test]
+    fn bbox_sphere_and_ray_queries() {
+        let a = Bbox::new(Vec3::new(0, 0, 0)).expand(Vec3::new(4, 4, 4));
+        let b = Bbox::new(Vec3::new(4, 2, -3)).expand(Vec3::new(8, 3, 1));
+        let c = Bbox::new(Vec3::new(5, 5, 5)).expand(Vec3::new(6, 6, 6));
+
+        assert_eq!(
+            a.intersection(&b),
+            Some(Bbox::new(Vec3::new(4, 2, 0)).expand(Vec3::new(4, 3, 1)))
+        );
+        assert!(a.overlaps(&b));
+        assert!(!a.overlaps(&c));
+        assert!(a.corners().iter().all(|p| a.contains(*p)));
+
+        let sphere = Sphere::bounding(&a);
+        assert_eq!(sphere.center, Vec3f::new(2.0, 2.0, 2.0));
+        assert!((sphere.radius - 12.0_f64.sqrt()).abs() < 1e-9);
+        assert!(sphere.contains(Vec3f::new(0.5, 0.5, 0.5)));
+        assert!(sphere.bbox().contains(Vec3::new(-1, 5, 2)));
+
+        let ray = Ray::new(Vec3f::new(-10.0, 2.0, 2.0), Vec3f::new(2.0, 0.0, 0.0));
+        assert_eq!(ray.intersect_bbox(&a), Some((5.0, 7.0)));
+        assert_eq!(ray.intersect_bbox(&c), None);
+        assert_eq!(ray.at(5.0), Vec3f::new(0.0, 2.0, 2.0));
+
+        let unit = Sphere::new(Vec3f::new(0.0, 2.0, 2.0), 1.0);
+        assert_eq!(ray.intersect_sphere(&unit), Some(4.5));
+        assert_eq!(
+            Ray::new(unit.center, ray.direction).intersect_sphere(&unit),
+            Some(0.0)
+        );
+        assert_eq!(
+            Ray::new(ray.origin, -ray.direction).intersect_sphere(&unit),
+            None
+        );
+
+        assert_eq!(
+            Vec3::new(1, 0, 0).cross(Vec3::new(0, 1, 0)),
+            Vec3::new(0, 0, 1)
+        );
+        assert_eq!(Vec3::new(1, 2, 3).dot(Vec3::new(4, 5, 6)), 32);
+        assert_eq!(
+            <[f64; 3]>::from(Vec3f::from([1.0, 2.0, 3.0])),
+            [1.0, 2.0, 3.0]
+        );
+    }
+}
@@ -0,0 +1,353 @@
+use hashbrown::HashMap;
+use rand::Rng;
+
+use crate::geo::{Vec3, Vec3f};
+use crate::{Dla, DlaError, Observer, Walker, WalkerState, MAX_SPAWN_ATTEMPTS};
+
+/// How new particles reach the DLA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum GrowthModel {
+    /// Walkers randomly diffuse until they stick, the classic DLA.
+    #[default]
+    Diffusion,
+
+    /// Walkers are launched from a random direction towards the DLA and fly
+    /// along straight lines until they stick, aka ballistic aggregation.
+    Ballistic,
+
+    /// A random empty site on the perimeter of the DLA is occupied at each
+    /// step, aka Eden growth.
+    Eden,
+
+    /// Walkers move one lattice site at a time to a random neighbor and stick
+    /// as soon as they're next to a cell, where neighbors are defined by the
+    /// given connectivity.
+    Lattice(Connectivity),
+}
+
+/// Which sites of the lattice are considered to be next to each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Connectivity {
+    /// Sites sharing a face.
+    Six,
+
+    /// Sites sharing a face or an edge.
+    Eighteen,
+
+    /// Sites sharing a face, an edge or a corner.
+    TwentySix,
+}
+
+/// The empty sites next to the cells of the DLA, used by the Eden model.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Perimeter {
+    sites: Vec<Vec3>,
+    index: HashMap<Vec3, usize>,
+
+    // number of cells of the DLA the perimeter was computed for, used to
+    // detect when cells were added by other models
+    cells: usize,
+}
+
+const NEIGHBORS: [Vec3; 6] = [
+    Vec3::new(1, 0, 0),
+    Vec3::new(-1, 0, 0),
+    Vec3::new(0, 1, 0),
+    Vec3::new(0, -1, 0),
+    Vec3::new(0, 0, 1),
+    Vec3::new(0, 0, -1),
+];
+
+const EDGE_NEIGHBORS: [Vec3; 12] = [
+    Vec3::new(1, 1, 0),
+    Vec3::new(1, -1, 0),
+    Vec3::new(-1, 1, 0),
+    Vec3::new(-1, -1, 0),
+    Vec3::new(1, 0, 1),
+    Vec3::new(1, 0, -1),
+    Vec3::new(-1, 0, 1),
+    Vec3::new(-1, 0, -1),
+    Vec3::new(0, 1, 1),
+    Vec3::new(0, 1, -1),
+    Vec3::new(0, -1, 1),
+    Vec3::new(0, -1, -1),
+];
+
+const CORNER_NEIGHBORS: [Vec3; 8] = [
+    Vec3::new(1, 1, 1),
+    Vec3::new(1, 1, -1),
+    Vec3::new(1, -1, 1),
+    Vec3::new(1, -1, -1),
+    Vec3::new(-1, 1, 1),
+    Vec3::new(-1, 1, -1),
+    Vec3::new(-1, -1, 1),
+    Vec3::new(-1, -1, -1),
+];
+
+impl Connectivity {
+    /// The offsets of the neighbors of a site.
+    pub fn neighbors(self) -> Vec<Vec3> {
+        let mut neighbors = NEIGHBORS.to_vec();
+
+        if self != Connectivity::Six {
+            neighbors.extend_from_slice(&EDGE_NEIGHBORS);
+        }
+        if self == Connectivity::TwentySix {
+            neighbors.extend_from_slice(&CORNER_NEIGHBORS);
+        }
+
+        neighbors
+    }
+
+    /// The maximum squared distance between two neighbors.
+    pub fn max_dist2(self) -> i64 {
+        match self {
+            Connectivity::Six => 1,
+            Connectivity::Eighteen => 2,
+            Connectivity::TwentySix => 3,
+        }
+    }
+}
+
+impl Dla {
+    /// Change how new particles reach the DLA. The default is
+    /// `GrowthModel::Diffusion`.
+    pub fn set_growth_model(&mut self, model: GrowthModel) {
+        self.growth_model = model;
+    }
+
+    pub fn growth_model(&self) -> GrowthModel {
+        self.growth_model
+    }
+
+    /// Place a ballistic walker on the sphere around the spawn area and aim it
+    /// at a random point of the DLA, giving up after `MAX_SPAWN_ATTEMPTS`
+    /// attempts to find a free starting point outside the sinks.
+    pub(crate) fn launch<R: Rng>(&self, walker: &mut Walker, rng: &mut R) -> Result<(), DlaError> {
+        let spawn_bbox = self.spawn_bbox();
+        let center = Vec3f::from(spawn_bbox.lower() + spawn_bbox.upper()) / 2.0;
+        let d = Vec3f::from(spawn_bbox.dimensions());
+        let radius = d.x.min(d.y).min(d.z) / 2.0;
+
+        for _ in 0..MAX_SPAWN_ATTEMPTS {
+            let start = center + random_unit_vector(rng) * radius;
+            let target = Vec3f::new(
+                rng.gen_range(self.bbox.lower().x..=self.bbox.upper().x) as f64,
+                rng.gen_range(self.bbox.lower().y..=self.bbox.upper().y) as f64,
+                rng.gen_range(self.bbox.lower().z..=self.bbox.upper().z) as f64,
+            );
+
+            let position = start.round();
+            if start == target || !self.is_free(position) || self.in_sink(position) {
+                continue;
+            }
+
+            walker.position = position;
+            walker.exact = start;
+            walker.heading = (target - start).normalized();
+            return Ok(());
+        }
+
+        Err(DlaError::NoFreeSpawnCell)
+    }
+
+    /// Occupy a random site on the perimeter of the DLA. Sites that cannot be
+    /// occupied because of obstacles or containers are discarded and the
+    /// walker keeps walking. It fails once no site is left on the perimeter.
+    pub(crate) fn step_eden<R: Rng>(
+        &mut self,
+        walker: &mut Walker,
+        rng: &mut R,
+        observer: &mut impl Observer,
+    ) -> Result<WalkerState, DlaError> {
+        if self.perimeter.as_ref().map(|p| p.cells) != Some(self.len()) {
+            self.perimeter = Some(self.build_perimeter());
+        }
+
+        walker.stats.steps += 1;
+        self.stats.steps += 1;
+
+        let perimeter = self.perimeter.as_mut().unwrap();
+        let site = perimeter
+            .remove_random(rng)
+            .ok_or(DlaError::NoFreePerimeterSite)?;
+
+        if !self.is_free(site) {
+            return Ok(walker.state);
+        }
+
+        let parent = NEIGHBORS
+            .iter()
+            .map(|d| self.domain.wrap(site + *d))
+            .find(|n| self.contains(*n))
+            .expect("perimeter sites are always next to a cell");
+
+        self.attach(walker, parent, site, observer);
+
+        let new_sites = NEIGHBORS
+            .iter()
+            .map(|d| self.domain.wrap(site + *d))
+            .filter(|n| !self.contains(*n) && self.is_free(*n))
+            .collect::<Vec<_>>();
+
+        let cells = self.len();
+        let perimeter = self.perimeter.as_mut().unwrap();
+        for n in new_sites {
+            perimeter.insert(n);
+        }
+        perimeter.cells = cells;
+
+        Ok(walker.state)
+    }
+
+    fn build_perimeter(&self) -> Perimeter {
+        let mut perimeter = Perimeter {
+            cells: self.len(),
+            ..Perimeter::default()
+        };
+
+        for c in self.cells() {
+            for d in &NEIGHBORS {
+                let n = self.domain.wrap(*c + *d);
+                if !self.contains(n) && self.is_free(n) {
+                    perimeter.insert(n);
+                }
+            }
+        }
+
+        perimeter
+    }
+}
+
+impl Perimeter {
+    fn insert(&mut self, p: Vec3) {
+        if !self.index.contains_key(&p) {
+            self.index.insert(p, self.sites.len());
+            self.sites.push(p);
+        }
+    }
+
+    fn remove_random<R: Rng>(&mut self, rng: &mut R) -> Option<Vec3> {
+        if self.sites.is_empty() {
+            return None;
+        }
+
+        let i = rng.gen_range(0..self.sites.len());
+        let p = self.sites.swap_remove(i);
+        self.index.remove(&p);
+        if let Some(moved) = self.sites.get(i) {
+            self.index.insert(*moved, i);
+        }
+
+        Some(p)
+    }
+}
+
+pub(crate) fn random_unit_vector<R: Rng>(rng: &mut R) -> Vec3f {
+    let z = rng.gen_range(-1.0..=1.0_f64);
+    let theta = rng.gen_range(0.0..std::f64::consts::TAU);
+    let r = (1.0 - z * z).sqrt();
+
+    Vec3f::new(r * theta.cos(), r * theta.sin(), z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::shape::Shape;
+    use crate::Event;
+
+    #[test]
+    fn eden_grows_a_connected_cluster() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.set_growth_model(GrowthModel::Eden);
+
+        for _ in 0..500 {
+            dla.add(&mut rng);
+        }
+
+        assert_eq!(dla.len(), 501);
+        for c in dla.cells() {
+            assert!(*c == Vec3::new(0, 0, 0) || NEIGHBORS.iter().any(|d| dla.contains(*c + *d)));
+        }
+    }
+
+    #[test]
+    fn eden_stops_once_the_perimeter_is_full() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let container = Shape::Sphere {
+            center: Vec3::new(0, 0, 0),
+            radius: 2,
+        };
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.set_growth_model(GrowthModel::Eden);
+        dla.add_container(container.clone());
+
+        let err = loop {
+            if let Err(e) = dla.add_with(&mut rng, &mut |_: &Event| {}) {
+                break e;
+            }
+        };
+
+        assert_eq!(err, DlaError::NoFreePerimeterSite);
+        let sites = (-2..=2)
+            .flat_map(|x| (-2..=2).flat_map(move |y| (-2..=2).map(move |z| Vec3::new(x, y, z))))
+            .filter(|p| container.contains(*p));
+        assert_eq!(dla.len(), sites.count());
+    }
+
+    #[test]
+    fn ballistic_walkers_stick_to_the_dla() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.set_growth_model(GrowthModel::Ballistic);
+
+        for _ in 0..500 {
+            let c = dla.add(&mut rng);
+            assert!(dla.contains(c));
+        }
+    }
+
+    #[test]
+    fn ballistic_launch_gives_up_when_blocked() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.set_growth_model(GrowthModel::Ballistic);
+
+        // the launch sphere is entirely outside the container
+        dla.add_container(Shape::Sphere {
+            center: Vec3::new(0, 0, 0),
+            radius: 3,
+        });
+
+        assert_eq!(
+            dla.add_with(&mut rng, &mut |_: &Event| {}),
+            Err(DlaError::NoFreeSpawnCell)
+        );
+    }
+
+    #[test]
+    fn lattice_walkers_stick_next_to_a_cell() {
+        for connectivity in [
+            Connectivity::Six,
+            Connectivity::Eighteen,
+            Connectivity::TwentySix,
+        ] {
+            let mut rng = StdRng::seed_from_u64(42);
+            let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+            dla.set_growth_model(GrowthModel::Lattice(connectivity));
+
+            let neighbors = connectivity.neighbors();
+            for _ in 0..200 {
+                let c = dla.add(&mut rng);
+                assert!(neighbors.iter().any(|d| dla.contains(c + *d)));
+            }
+        }
+    }
+}
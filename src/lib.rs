@@ -1,23 +1,217 @@
-use rand::Rng;
+use std::time::{Duration, Instant};
 
+use hashbrown::HashMap;
+use rand::{Rng, SeedableRng};
+
+pub mod anisotropy;
+pub mod builder;
+pub use builder::{DlaBuilder, DlaError};
+pub mod compose;
+pub mod dlca;
+pub mod domain;
+pub mod dump;
+pub use domain::Domain;
 pub mod geo;
 pub use geo::Vec3;
+pub mod growth;
+pub use growth::GrowthModel;
 pub mod octree;
+pub mod offlattice;
+pub mod shape;
+pub mod species;
+pub mod stop;
+pub use stop::StopCondition;
 
-use crate::geo::Bbox;
+use crate::anisotropy::Anisotropy;
+use crate::geo::{Bbox, Vec3f};
+use crate::growth::Perimeter;
 use crate::octree::Octree;
+use crate::shape::Shape;
+use crate::species::{Species, SpeciesId};
+
+/// How many random cells are tried when looking for a free cell where to
+/// spawn a walker before giving up with `DlaError::NoFreeSpawnCell`.
+pub const MAX_SPAWN_ATTEMPTS: u32 = 100_000;
 
 #[derive(Debug, Clone)]
 pub struct Dla {
     spawn_radius: i64,
     attraction_radius: i64,
-    attraction_radius2: i64,
 
     cells: Octree,
     bbox: Bbox,
+
+    obstacles: Vec<Shape>,
+    containers: Vec<Shape>,
+    attractors: Vec<Attractor>,
+    sinks: Vec<Shape>,
+    stickiness: f64,
+
+    anisotropy: Option<Anisotropy>,
+    // how many times the sites not yet occupied have been hit, only used when
+    // the anisotropy requires many hits
+    hits: HashMap<Vec3, u32>,
+
+    species: Vec<Species>,
+    // only cells whose species is not the default one are stored
+    cell_species: HashMap<Vec3, SpeciesId>,
+
+    domain: Domain,
+
+    growth_model: GrowthModel,
+    // lazily built the first time the Eden model is used
+    perimeter: Option<Perimeter>,
+
+    stats: Stats,
+    collect_stats: bool,
+}
+
+/// Counters collected while the walkers wander around looking for a cell to
+/// stick to.
+///
+/// The totals are always kept up to date, while `particles` and `nearest_time`
+/// are only collected when enabled with `Dla::collect_stats` because they're
+/// relatively expensive to track.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Total number of random walk steps taken by all the walkers.
+    pub steps: u64,
+
+    /// Total number of times a walker left the spawn area, or was absorbed by
+    /// a sink, and had to be respawned.
+    pub respawns: u64,
+
+    /// How many of the respawns were caused by a sink.
+    pub absorbed: u64,
+
+    /// Total time spent looking for the nearest cell in the octree.
+    pub nearest_time: Duration,
+
+    /// Number of walkers that stuck to a site that was already occupied and
+    /// merged with its cell instead of adding a new one.
+    pub merged: u64,
+
+    /// Statistics of every new cell added while collection was enabled, in
+    /// insertion order. Walkers merged with an existing cell are not included.
+    pub particles: Vec<ParticleStats>,
+}
+
+/// A region that steers the growth of the DLA towards itself by biasing the
+/// motion of the walkers and by making particles close to it stickier.
+#[derive(Debug, Clone)]
+pub struct Attractor {
+    pub shape: Shape,
+
+    /// Probability, between 0 and 1, that a walker moves towards the shape
+    /// along each axis instead of moving randomly.
+    pub pull: f64,
+
+    /// Probability, between 0 and 1, that a particle sticks to the DLA when
+    /// it's closer than `range` to the shape. It's only used when it's higher
+    /// than the stickiness of the DLA.
+    pub stickiness: f64,
+
+    pub range: f64,
+}
+
+/// A particle that randomly walks around the DLA until it sticks to it.
+#[derive(Debug, Clone)]
+pub struct Walker {
+    position: Vec3,
+    species: SpeciesId,
+    // exact position and direction of ballistic walkers that move along
+    // straight lines
+    exact: Vec3f,
+    heading: Vec3f,
+    state: WalkerState,
+    stats: ParticleStats,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkerState {
+    /// The walker is still looking for a cell to stick to.
+    Walking,
+
+    /// The walker stuck to the DLA and became the given cell.
+    Stuck(Vec3),
+}
+
+/// Something interesting that happened while growing the DLA.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// A walker of the given species stuck to the `parent` cell and became
+    /// the new `cell`.
+    Attached {
+        cell: Vec3,
+        parent: Vec3,
+        species: SpeciesId,
+    },
+
+    /// A walker left the spawn area at `escaped_at`, or hit a site that needs
+    /// more hits before being occupied, and was moved to `position`.
+    Respawned { escaped_at: Vec3, position: Vec3 },
+
+    /// A walker was absorbed by a sink at `absorbed_at` and was respawned at
+    /// `position`.
+    Absorbed { absorbed_at: Vec3, position: Vec3 },
+
+    /// The root of the octree holding the cells had to grow to fit a new cell.
+    OctreeRebuilt { rebuilt_count: usize },
+}
+
+/// An observer is notified about the events happening while the DLA grows.
+///
+/// Any `FnMut(&Event)` closure is an observer.
+pub trait Observer {
+    fn notify(&mut self, event: &Event);
+
+    /// Whether the run should stop, checked by `Dla::run_until_with` after
+    /// each particle. Observers that can fail use it to stop the growth as
+    /// soon as they do.
+    fn interrupted(&self) -> bool {
+        false
+    }
+}
+
+impl<F: FnMut(&Event)> Observer for F {
+    fn notify(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Statistics about the random walk of a single particle.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ParticleStats {
+    pub steps: u64,
+    pub respawns: u64,
+    pub nearest_time: Duration,
+}
+
+impl Stats {
+    /// Average and maximum number of steps taken by the particles whose
+    /// statistics were collected, `None` if there are none.
+    pub fn particle_steps(&self) -> Option<(f64, u64)> {
+        average_and_max(self.particles.iter().map(|p| p.steps))
+    }
+
+    /// Average and maximum number of respawns of the particles whose
+    /// statistics were collected, `None` if there are none.
+    pub fn particle_respawns(&self) -> Option<(f64, u64)> {
+        average_and_max(self.particles.iter().map(|p| p.respawns))
+    }
+}
+
+fn average_and_max(values: impl ExactSizeIterator<Item = u64>) -> Option<(f64, u64)> {
+    let n = values.len() as f64;
+    let (sum, max) = values.fold((0, None), |(sum, max), v| (sum + v, max.max(Some(v))));
+
+    max.map(|max| (sum as f64 / n, max))
 }
 
 impl Dla {
+    /// Create a DLA growing from the given seeds, returning `None` if there
+    /// are none. The parameters are not validated, use `DlaBuilder` to catch
+    /// attraction radii too small for the growth model and the like.
     pub fn new(
         spawn_radius: u32,
         attraction_radius: u16,
@@ -34,7 +228,20 @@ impl Dla {
             bbox,
             spawn_radius: i64::from(spawn_radius),
             attraction_radius: i64::from(attraction_radius),
-            attraction_radius2: i64::from(attraction_radius).pow(2),
+            obstacles: vec![],
+            containers: vec![],
+            attractors: vec![],
+            sinks: vec![],
+            stickiness: 1.0,
+            anisotropy: None,
+            hits: HashMap::new(),
+            species: vec![],
+            cell_species: HashMap::new(),
+            domain: Domain::Unbounded,
+            growth_model: GrowthModel::Diffusion,
+            perimeter: None,
+            stats: Stats::default(),
+            collect_stats: false,
         })
     }
 
@@ -46,6 +253,11 @@ impl Dla {
         self.cells.len()
     }
 
+    /// Whether the given point is a cell of the DLA.
+    pub fn contains(&self, p: Vec3) -> bool {
+        self.cells.nearest(p).is_some_and(|(_, d2)| d2 == 0)
+    }
+
     pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
@@ -54,60 +266,976 @@ impl Dla {
         self.bbox.clone()
     }
 
+    pub fn spawn_radius(&self) -> i64 {
+        self.spawn_radius
+    }
+
+    pub fn attraction_radius(&self) -> i64 {
+        self.attraction_radius
+    }
+
+    pub fn octree(&self) -> &Octree {
+        &self.cells
+    }
+
+    pub fn stats(&self) -> &Stats {
+        &self.stats
+    }
+
+    /// Enable or disable the collection of per particle statistics and timings.
+    pub fn collect_stats(&mut self, enabled: bool) {
+        self.collect_stats = enabled;
+    }
+
+    /// Add a new particle to the DLA by letting a walker wander around until it
+    /// sticks to the DLA. Note that this can take an arbitrary amount of time,
+    /// see `try_add` for a bounded version.
+    ///
+    /// It panics if the DLA cannot grow anymore, for example when no free cell
+    /// to spawn the walker in can be found, see `add_with` for a version that
+    /// returns the error instead.
     pub fn add<R: Rng>(&mut self, rng: &mut R) -> Vec3 {
-        let spawn_bbox = self
-            .bbox
-            .expand(self.bbox.lower() - self.spawn_radius)
-            .expand(self.bbox.upper() + self.spawn_radius);
-
-        let respawn_cell = |rng: &mut R| {
-            Vec3::new(
-                rng.gen_range(spawn_bbox.lower().x..=spawn_bbox.upper().x),
-                rng.gen_range(spawn_bbox.lower().y..=spawn_bbox.upper().y),
-                rng.gen_range(spawn_bbox.lower().z..=spawn_bbox.upper().z),
-            )
-        };
+        self.add_with(rng, &mut |_: &Event| {})
+            .unwrap_or_else(|e| panic!("cannot add a particle to the DLA: {}", e))
+    }
 
-        let mut cell = respawn_cell(rng);
+    /// Same as `add`, but notify the given observer about what happens while
+    /// the particle is added. It fails instead of panicking if the DLA cannot
+    /// grow anymore, for example when no free cell to spawn the walker in can
+    /// be found after `MAX_SPAWN_ATTEMPTS` attempts.
+    pub fn add_with<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        observer: &mut impl Observer,
+    ) -> Result<Vec3, DlaError> {
+        let mut walker = self.spawn_walker(rng)?;
 
         loop {
-            match self.stuck(cell) {
-                Some(n) => {
-                    let d = cell - n;
-                    cell = n + Vec3::new(d.x.signum(), d.y.signum(), d.z.signum());
+            if let WalkerState::Stuck(cell) = self.step_walker_with(&mut walker, rng, observer)? {
+                break Ok(cell);
+            }
+        }
+    }
+
+    /// Try to add a new particle to the DLA by letting a walker wander for at
+    /// most `max_steps` steps. Return the cell the walker stuck to, if any.
+    pub fn try_add<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        max_steps: u64,
+    ) -> Result<Option<Vec3>, DlaError> {
+        self.try_add_with(rng, max_steps, &mut |_: &Event| {})
+    }
 
-                    self.cells.add(cell);
-                    self.bbox = self.bbox.expand(cell);
+    /// Same as `try_add`, but notify the given observer about what happens
+    /// while the walker wanders around.
+    pub fn try_add_with<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        max_steps: u64,
+        observer: &mut impl Observer,
+    ) -> Result<Option<Vec3>, DlaError> {
+        let mut walker = self.spawn_walker(rng)?;
 
-                    break;
+        for _ in 0..max_steps {
+            if let WalkerState::Stuck(cell) = self.step_walker_with(&mut walker, rng, observer)? {
+                return Ok(Some(cell));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Spawn a new walker at a random free position around the DLA. The
+    /// walker can be then moved one step at a time with `step_walker`.
+    pub fn spawn_walker<R: Rng>(&self, rng: &mut R) -> Result<Walker, DlaError> {
+        let species = self.random_species(rng);
+        let position = self.random_free_cell(&self.spawn_bbox(), rng)?;
+
+        let mut walker = Walker {
+            position,
+            species,
+            exact: Vec3f::from(position),
+            heading: Vec3f::default(),
+            state: WalkerState::Walking,
+            stats: ParticleStats::default(),
+        };
+
+        if self.growth_model == GrowthModel::Ballistic {
+            self.launch(&mut walker, rng)?;
+        }
+
+        Ok(walker)
+    }
+
+    /// Move the given walker by one step, attaching it to the DLA if it's close
+    /// enough to a cell. Walkers that already stuck are left untouched. It
+    /// fails if the walker needs to be respawned but there's no free cell to
+    /// respawn it in.
+    pub fn step_walker<R: Rng>(
+        &mut self,
+        walker: &mut Walker,
+        rng: &mut R,
+    ) -> Result<WalkerState, DlaError> {
+        self.step_walker_with(walker, rng, &mut |_: &Event| {})
+    }
+
+    /// Same as `step_walker`, but notify the given observer about what happens
+    /// during the step.
+    pub fn step_walker_with<R: Rng>(
+        &mut self,
+        walker: &mut Walker,
+        rng: &mut R,
+        observer: &mut impl Observer,
+    ) -> Result<WalkerState, DlaError> {
+        if walker.state != WalkerState::Walking {
+            return Ok(walker.state);
+        }
+
+        if self.growth_model == GrowthModel::Eden {
+            return self.step_eden(walker, rng, observer);
+        }
+
+        let attraction_radius = self.species_attraction_radius(walker.species);
+
+        // on a lattice walkers stick as soon as they're next to a cell
+        let max_dist2 = match self.growth_model {
+            GrowthModel::Lattice(connectivity) => connectivity.max_dist2(),
+            _ => attraction_radius.pow(2),
+        };
+
+        let stuck = if self.collect_stats {
+            let start = Instant::now();
+            let stuck = self.stuck_within(walker.position, max_dist2);
+            let elapsed = start.elapsed();
+
+            walker.stats.nearest_time += elapsed;
+            self.stats.nearest_time += elapsed;
+
+            stuck
+        } else {
+            self.stuck_within(walker.position, max_dist2)
+        };
+
+        // particles cannot stick inside obstacles or outside containers
+        let stuck = stuck
+            .and_then(|n| {
+                let d = self.domain.min_image(walker.position - n);
+                match self.growth_model {
+                    GrowthModel::Lattice(_) => {
+                        Some((n, walker.position, d)).filter(|_| d != Vec3::new(0, 0, 0))
+                    }
+                    _ => {
+                        let cell = n + Vec3::new(d.x.signum(), d.y.signum(), d.z.signum());
+                        Some((n, self.domain.wrap(cell), d))
+                    }
                 }
-                None => {
-                    let mut motion = || {
-                        let d = if rng.gen::<f32>() < 0.5 { -1 } else { 1 };
-                        rng.gen_range(1..self.attraction_radius / 2) * d
-                    };
+            })
+            .filter(|(_, cell, _)| self.is_free(*cell))
+            .filter(|(n, cell, d)| {
+                let mut stickiness = self.stickiness_at(*cell);
+                if let Some(species) = self.species.get(walker.species) {
+                    stickiness *= species.stickiness_to(self.species_of(*n));
+                }
+                if let Some(anisotropy) = &self.anisotropy {
+                    stickiness *= anisotropy.stickiness(*d);
+                }
+
+                stickiness >= 1.0 || rng.gen::<f64>() < stickiness
+            });
+
+        // sites that need many hits reject the walkers until they're hit
+        // enough times
+        let rejected = stuck.is_some_and(|(_, cell, _)| !self.hit(cell));
+
+        match stuck.filter(|_| !rejected) {
+            Some((n, cell, _)) => self.attach(walker, n, cell, observer),
+            None => {
+                let mut escaped = rejected;
 
-                    let d = Vec3::new(motion(), motion(), motion());
+                if rejected {
+                    // the walker is respawned below
+                } else if self.growth_model == GrowthModel::Ballistic {
+                    // move by half the attraction radius to avoid flying
+                    // through cells
+                    walker.exact = walker.exact + walker.heading * (attraction_radius as f64 / 2.0);
 
-                    cell = cell + d * self.attraction_radius;
+                    // ballistic walkers cannot bounce, they just fly away
+                    let position = walker.exact.round();
+                    let wrapped = self.domain.wrap(position);
+                    walker.exact = walker.exact + Vec3f::from(wrapped - position);
 
-                    if !spawn_bbox.contains(cell) {
-                        cell = respawn_cell(rng);
+                    escaped = !self.is_free(wrapped);
+                    walker.position = wrapped;
+                } else if let GrowthModel::Lattice(connectivity) = self.growth_model {
+                    let neighbors = connectivity.neighbors();
+                    let position = self
+                        .domain
+                        .wrap(walker.position + neighbors[rng.gen_range(0..neighbors.len())]);
+
+                    // walkers spawned over a cell cannot move through the DLA,
+                    // respawn them instead
+                    escaped = self.contains(walker.position);
+                    if self.is_free(position) {
+                        walker.position = position;
+                    }
+                } else {
+                    let (toward, pull) = self.drift(walker.position);
+
+                    let mut motion = |toward: i64| {
+                        let d = if toward != 0 && pull > 0.0 && rng.gen::<f64>() < pull {
+                            toward
+                        } else if rng.gen::<f32>() < 0.5 {
+                            -1
+                        } else {
+                            1
+                        };
+                        rng.gen_range(1..attraction_radius / 2) * d
+                    };
+
+                    let d = Vec3::new(motion(toward.x), motion(toward.y), motion(toward.z));
+
+                    // walkers bounce off obstacles and containers by just
+                    // staying where they are, note that thin obstacles might
+                    // be jumped over
+                    let position = self.domain.wrap(walker.position + d * attraction_radius);
+                    if self.is_free(position) {
+                        walker.position = position;
                     }
                 }
+
+                walker.stats.steps += 1;
+                self.stats.steps += 1;
+
+                let spawn_bbox = self.spawn_bbox();
+                if self.in_sink(walker.position) {
+                    let absorbed_at = walker.position;
+
+                    self.respawn(walker, &spawn_bbox, rng)?;
+                    walker.stats.respawns += 1;
+                    self.stats.respawns += 1;
+                    self.stats.absorbed += 1;
+
+                    observer.notify(&Event::Absorbed {
+                        absorbed_at,
+                        position: walker.position,
+                    });
+                } else if escaped || !spawn_bbox.contains(walker.position) {
+                    let escaped_at = walker.position;
+
+                    self.respawn(walker, &spawn_bbox, rng)?;
+                    walker.stats.respawns += 1;
+                    self.stats.respawns += 1;
+
+                    observer.notify(&Event::Respawned {
+                        escaped_at,
+                        position: walker.position,
+                    });
+                }
+            }
+        }
+
+        Ok(walker.state)
+    }
+
+    /// Turn the walker into a new cell of the DLA stuck to the `parent` cell.
+    fn attach(
+        &mut self,
+        walker: &mut Walker,
+        parent: Vec3,
+        cell: Vec3,
+        observer: &mut impl Observer,
+    ) {
+        let rebuilt_count = self.cells.rebuilt_count();
+        let len = self.cells.len();
+        self.cells.add(cell);
+        self.bbox = self.bbox.expand(cell);
+
+        // walkers can stick to a site that's already occupied, in which case
+        // they merge with the existing cell and there's no new cell to report
+        let is_new = self.cells.len() != len;
+        if is_new && walker.species != 0 {
+            self.cell_species.insert(cell, walker.species);
+        }
+
+        walker.position = cell;
+        walker.state = WalkerState::Stuck(cell);
+
+        if !is_new {
+            self.stats.merged += 1;
+        } else if self.collect_stats {
+            self.stats.particles.push(walker.stats.clone());
+        }
+
+        if is_new {
+            observer.notify(&Event::Attached {
+                cell,
+                parent,
+                species: walker.species,
+            });
+        }
+        if self.cells.rebuilt_count() != rebuilt_count {
+            observer.notify(&Event::OctreeRebuilt {
+                rebuilt_count: self.cells.rebuilt_count(),
+            });
+        }
+    }
+
+    /// Register a hit of a walker on the given site and return whether the
+    /// site has been hit enough times to be occupied.
+    fn hit(&mut self, site: Vec3) -> bool {
+        let needed = self.anisotropy.as_ref().map_or(1, |a| a.hits);
+        if needed <= 1 {
+            return true;
+        }
+
+        let hits = self.hits.entry(site).or_insert(0);
+        *hits += 1;
+        if *hits < needed {
+            return false;
+        }
+
+        self.hits.remove(&site);
+        true
+    }
+
+    fn respawn<R: Rng>(
+        &self,
+        walker: &mut Walker,
+        spawn_bbox: &Bbox,
+        rng: &mut R,
+    ) -> Result<(), DlaError> {
+        if self.growth_model == GrowthModel::Ballistic {
+            self.launch(walker, rng)?;
+        } else {
+            walker.position = self.random_free_cell(spawn_bbox, rng)?;
+        }
+
+        Ok(())
+    }
+
+    /// Add an obstacle walkers cannot enter and particles cannot stick into.
+    pub fn add_obstacle(&mut self, obstacle: Shape) {
+        self.obstacles.push(obstacle);
+    }
+
+    /// Add a container walkers cannot leave and particles cannot stick
+    /// outside of. When there are many containers, walkers are confined to
+    /// their intersection.
+    pub fn add_container(&mut self, container: Shape) {
+        self.containers.push(container);
+    }
+
+    pub fn obstacles(&self) -> &[Shape] {
+        &self.obstacles
+    }
+
+    pub fn containers(&self) -> &[Shape] {
+        &self.containers
+    }
+
+    /// Add an attractor that steers the growth towards itself.
+    pub fn add_attractor(&mut self, attractor: Attractor) {
+        self.attractors.push(attractor);
+    }
+
+    /// Add a sink that absorbs the walkers that enter it.
+    pub fn add_sink(&mut self, sink: Shape) {
+        self.sinks.push(sink);
+    }
+
+    pub fn attractors(&self) -> &[Attractor] {
+        &self.attractors
+    }
+
+    pub fn sinks(&self) -> &[Shape] {
+        &self.sinks
+    }
+
+    /// Set the probability, between 0 and 1, that a walker sticks to the DLA
+    /// once it's close enough to a cell. By default walkers always stick.
+    pub fn set_stickiness(&mut self, stickiness: f64) {
+        self.stickiness = stickiness;
+    }
+
+    pub fn stickiness(&self) -> f64 {
+        self.stickiness
+    }
+
+    /// Probability that a particle sticks to the DLA at the given cell, taking
+    /// into account the nearby attractors.
+    pub fn stickiness_at(&self, p: Vec3) -> f64 {
+        self.attractors
+            .iter()
+            .filter(|a| a.shape.distance(p) <= a.range)
+            .map(|a| a.stickiness)
+            .fold(self.stickiness, f64::max)
+    }
+
+    /// Set the anisotropy that makes the attachment of particles depend on the
+    /// direction they come from, `None` makes the growth isotropic.
+    pub fn set_anisotropy(&mut self, anisotropy: Option<Anisotropy>) {
+        self.anisotropy = anisotropy;
+        self.hits.clear();
+    }
+
+    pub fn anisotropy(&self) -> Option<&Anisotropy> {
+        self.anisotropy.as_ref()
+    }
+
+    /// Direction, as the sign of each axis, towards the closest attractor and
+    /// how strongly a walker in `p` is pulled towards it.
+    fn drift(&self, p: Vec3) -> (Vec3, f64) {
+        let closest = self
+            .attractors
+            .iter()
+            .filter(|a| a.pull > 0.0)
+            .map(|a| (a, a.shape.distance(p)))
+            .min_by(|(_, d1), (_, d2)| d1.total_cmp(d2));
+
+        let (attractor, d) = match closest {
+            None => return (Vec3::new(0, 0, 0), 0.0),
+            Some(c) => c,
+        };
+
+        if d <= 0.0 {
+            return (Vec3::new(0, 0, 0), 0.0);
+        }
+
+        // approximate the gradient of the distance field with central
+        // differences, moving against it goes towards the shape
+        let h = self.attraction_radius;
+        let gradient =
+            |dp: Vec3| attractor.shape.distance(p + dp) - attractor.shape.distance(p - dp);
+        let toward = |g: f64| -(g.signum() as i64) * i64::from(g != 0.0);
+
+        let toward = Vec3::new(
+            toward(gradient(Vec3::new(h, 0, 0))),
+            toward(gradient(Vec3::new(0, h, 0))),
+            toward(gradient(Vec3::new(0, 0, h))),
+        );
+
+        (toward, attractor.pull)
+    }
+
+    /// Set the domain the DLA grows in. Cells already in the DLA are not moved,
+    /// hence they should already be inside the domain.
+    pub fn set_domain(&mut self, domain: Domain) {
+        self.domain = domain;
+        self.perimeter = None;
+    }
+
+    pub fn domain(&self) -> &Domain {
+        &self.domain
+    }
+
+    /// Whether the given point is outside all the obstacles and inside all the
+    /// containers.
+    pub fn is_free(&self, p: Vec3) -> bool {
+        p.is_bounded()
+            && self.obstacles.iter().all(|o| !o.contains(p))
+            && self.containers.iter().all(|c| c.contains(p))
+    }
+
+    /// Whether the given point is inside any of the sinks.
+    pub fn in_sink(&self, p: Vec3) -> bool {
+        self.sinks.iter().any(|s| s.contains(p))
+    }
+
+    /// Pick a random free cell outside the sinks inside the given bbox, giving
+    /// up after `MAX_SPAWN_ATTEMPTS` attempts.
+    fn random_free_cell<R: Rng>(&self, bbox: &Bbox, rng: &mut R) -> Result<Vec3, DlaError> {
+        (0..MAX_SPAWN_ATTEMPTS)
+            .map(|_| random_cell(bbox, rng))
+            .find(|p| self.is_free(*p) && !self.in_sink(*p))
+            .ok_or(DlaError::NoFreeSpawnCell)
+    }
+
+    /// Check that the seeds are free and that walkers can be spawned around
+    /// them. The spawn area is only sampled, hence it might still be too
+    /// crowded to find a free cell later on.
+    pub(crate) fn check_free_space(&self) -> Result<(), DlaError> {
+        if let Some(seed) = self.cells().find(|c| !self.is_free(**c)) {
+            return Err(DlaError::SeedNotFree(*seed));
+        }
+
+        // always sample the same cells so that building is deterministic
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        self.spawn_walker(&mut rng)?;
+
+        Ok(())
+    }
+
+    fn spawn_bbox(&self) -> Bbox {
+        if let Domain::Periodic(bbox) = &self.domain {
+            return bbox.clone();
+        }
+
+        // huge spawn radii are scaled down so that walkers never go beyond
+        // the coordinates where distances are safe to compute
+        self.bbox
+            .expand(self.bbox.lower() - self.spawn_radius)
+            .expand(self.bbox.upper() + self.spawn_radius)
+            .clamped(&Bbox::bounds())
+    }
+
+    /// Set the species of the particles. Seeds and cells added before are
+    /// considered to be of the first species, the id of a species is its index
+    /// in the given `Vec`.
+    pub fn set_species(&mut self, species: Vec<Species>) {
+        self.species = species;
+    }
+
+    pub fn species(&self) -> &[Species] {
+        &self.species
+    }
+
+    /// The species of the given cell of the DLA.
+    pub fn species_of(&self, cell: Vec3) -> SpeciesId {
+        self.cell_species.get(&cell).copied().unwrap_or(0)
+    }
+
+    fn species_attraction_radius(&self, species: SpeciesId) -> i64 {
+        self.species
+            .get(species)
+            .map_or(self.attraction_radius, |s| i64::from(s.attraction_radius))
+    }
+
+    fn random_species<R: Rng>(&self, rng: &mut R) -> SpeciesId {
+        if self.species.len() <= 1 {
+            return 0;
+        }
+
+        let total = self.species.iter().map(|s| s.spawn_ratio).sum::<f64>();
+        let mut r = rng.gen::<f64>() * total;
+
+        for (i, s) in self.species.iter().enumerate() {
+            if r < s.spawn_ratio {
+                return i;
             }
+            r -= s.spawn_ratio;
         }
 
-        cell
+        self.species.len() - 1
     }
 
     pub fn stuck(&self, p: Vec3) -> Option<Vec3> {
-        let (n, d2) = self.cells.nearest(p)?;
+        self.stuck_within(p, self.attraction_radius.pow(2))
+    }
 
-        if d2 <= self.attraction_radius2 {
+    fn stuck_within(&self, p: Vec3, max_dist2: i64) -> Option<Vec3> {
+        let (n, d2) = self.cells.nearest_in(p, &self.domain)?;
+
+        if d2 <= max_dist2 {
             Some(n)
         } else {
             None
         }
     }
 }
+
+impl Walker {
+    pub fn position(&self) -> Vec3 {
+        self.position
+    }
+
+    pub fn state(&self) -> WalkerState {
+        self.state
+    }
+
+    pub fn species(&self) -> SpeciesId {
+        self.species
+    }
+
+    pub fn stats(&self) -> &ParticleStats {
+        &self.stats
+    }
+}
+
+/// A DLA is serialized as its parameters and its cells along with their
+/// species. Obstacles, containers, attractors and sinks are not serialized
+/// since shapes can be arbitrary functions, neither are the statistics.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Dla {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        // maps with non string keys are not supported by all the formats
+        struct CellSpecies<'d>(&'d HashMap<Vec3, SpeciesId>);
+
+        impl serde::Serialize for CellSpecies<'_> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq(self.0.iter())
+            }
+        }
+
+        let mut s = serializer.serialize_struct("Dla", 9)?;
+        s.serialize_field("spawn_radius", &self.spawn_radius)?;
+        s.serialize_field("attraction_radius", &self.attraction_radius)?;
+        s.serialize_field("stickiness", &self.stickiness)?;
+        s.serialize_field("growth_model", &self.growth_model)?;
+        s.serialize_field("domain", &self.domain)?;
+        s.serialize_field("anisotropy", &self.anisotropy)?;
+        s.serialize_field("species", &self.species)?;
+        s.serialize_field("cells", &self.cells)?;
+        s.serialize_field("cell_species", &CellSpecies(&self.cell_species))?;
+        s.end()
+    }
+}
+
+/// Deserialized DLAs go through `DlaBuilder`, hence they're validated like
+/// any other DLA.
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Dla {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+
+        #[derive(serde::Deserialize)]
+        struct DlaData {
+            spawn_radius: u32,
+            attraction_radius: u16,
+            stickiness: f64,
+            growth_model: GrowthModel,
+            domain: Domain,
+            anisotropy: Option<Anisotropy>,
+            species: Vec<Species>,
+            cells: CellsData,
+            cell_species: Vec<(Vec3, SpeciesId)>,
+        }
+
+        // the cells are serialized as an octree, but only the points are
+        // needed to build the octree of the DLA
+        #[derive(serde::Deserialize)]
+        struct CellsData {
+            points: Vec<Vec3>,
+        }
+
+        let data = DlaData::deserialize(deserializer)?;
+        let species = data.species.len().max(1);
+
+        let mut dla = DlaBuilder::new()
+            .spawn_radius(data.spawn_radius)
+            .attraction_radius(data.attraction_radius)
+            .stickiness(data.stickiness)
+            .growth_model(data.growth_model)
+            .domain(data.domain)
+            .anisotropy(data.anisotropy)
+            .species(data.species)
+            .seeds(data.cells.points)
+            .build()
+            .map_err(D::Error::custom)?;
+
+        for (c, s) in data.cell_species {
+            if !dla.contains(c) {
+                return Err(D::Error::custom(format!(
+                    "cell ({},{},{}) has a species but it's not part of the DLA",
+                    c.x, c.y, c.z
+                )));
+            }
+            if s >= species {
+                return Err(D::Error::custom(format!(
+                    "cell ({},{},{}) belongs to unknown species {}",
+                    c.x, c.y, c.z, s
+                )));
+            }
+            if s != 0 {
+                dla.cell_species.insert(c, s);
+            }
+        }
+
+        Ok(dla)
+    }
+}
+
+fn random_cell<R: Rng>(bbox: &Bbox, rng: &mut R) -> Vec3 {
+    Vec3::new(
+        rng.gen_range(bbox.lower().x..=bbox.upper().x),
+        rng.gen_range(bbox.lower().y..=bbox.upper().y),
+        rng.gen_range(bbox.lower().z..=bbox.upper().z),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use crate::geo::MAX_COORD;
+
+    #[test]
+    fn try_add_respects_step_budget() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+
+        assert_eq!(dla.try_add(&mut rng, 0), Ok(None));
+        assert_eq!(dla.len(), 1);
+
+        let cell = dla.try_add(&mut rng, 10_000).unwrap().unwrap();
+        assert_eq!(dla.len(), 2);
+        assert!(dla.contains(cell));
+        assert!((1..=3).contains(&cell.dist2(Vec3::new(0, 0, 0))));
+
+        let mut walker = dla.spawn_walker(&mut rng).unwrap();
+        let cell = loop {
+            if let WalkerState::Stuck(cell) = dla.step_walker(&mut walker, &mut rng).unwrap() {
+                break cell;
+            }
+        };
+
+        assert_eq!(dla.len(), 3);
+        assert_eq!(walker.position(), cell);
+        assert_eq!(
+            dla.step_walker(&mut walker, &mut rng),
+            Ok(WalkerState::Stuck(cell))
+        );
+        assert_eq!(dla.len(), 3);
+    }
+
+    #[test]
+    fn particle_stats_ignore_particles_added_without_collection() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        assert_eq!(dla.stats().particle_steps(), None);
+
+        for _ in 0..20 {
+            dla.add(&mut rng);
+        }
+        let uncollected_steps = dla.stats().steps;
+
+        dla.collect_stats(true);
+        for _ in 0..10 {
+            dla.add(&mut rng);
+        }
+
+        let stats = dla.stats();
+        let steps = stats.particles.iter().map(|p| p.steps).collect::<Vec<_>>();
+        assert_eq!(stats.particles.len(), 10);
+        assert_eq!(steps.iter().sum::<u64>(), stats.steps - uncollected_steps);
+        assert_eq!(
+            stats.particle_steps(),
+            Some((
+                steps.iter().sum::<u64>() as f64 / 10.0,
+                *steps.iter().max().unwrap()
+            ))
+        );
+        assert!(stats.particle_respawns().is_some());
+    }
+
+    #[test]
+    fn attached_events_report_new_cells_only() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.collect_stats(true);
+
+        let mut attached = hashbrown::HashSet::new();
+        for _ in 0..2000 {
+            dla.add_with(&mut rng, &mut |e: &Event| {
+                if let Event::Attached { cell, .. } = e {
+                    assert!(attached.insert(*cell), "{:?} attached twice", cell);
+                }
+            })
+            .unwrap();
+        }
+
+        assert_eq!(attached.len() + 1, dla.len());
+        assert!(dla.stats().merged > 0);
+        assert_eq!(dla.stats().particles.len() + 1, dla.len());
+    }
+
+    #[test]
+    fn obstacles_and_containers_confine_the_growth() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let obstacle = Shape::Sphere {
+            center: Vec3::new(6, 0, 0),
+            radius: 4,
+        };
+        let container = Shape::Sphere {
+            center: Vec3::new(0, 0, 0),
+            radius: 15,
+        };
+
+        let mut dla = DlaBuilder::new()
+            .obstacle(obstacle.clone())
+            .container(container.clone())
+            .build()
+            .unwrap();
+        for _ in 0..300 {
+            dla.add(&mut rng);
+        }
+
+        assert!(dla.len() > 100);
+        assert!(dla.cells().all(|c| dla.is_free(*c)));
+        assert!(dla.cells().all(|c| !obstacle.contains(*c)));
+        assert!(dla.cells().all(|c| container.contains(*c)));
+    }
+
+    #[test]
+    fn attractors_pull_the_growth_towards_them() {
+        let (mut right, mut left) = (0, 0);
+
+        // walkers still stick wherever they first meet the DLA, hence the
+        // bias only shows over a few runs
+        for seed in 0..4 {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut dla = DlaBuilder::new()
+                .attractor(Attractor {
+                    shape: Shape::Sphere {
+                        center: Vec3::new(60, 0, 0),
+                        radius: 4,
+                    },
+                    pull: 1.0,
+                    stickiness: 1.0,
+                    range: 0.0,
+                })
+                .build()
+                .unwrap();
+            for _ in 0..300 {
+                dla.add(&mut rng);
+            }
+
+            right += dla.cells().filter(|c| c.x > 0).count();
+            left += dla.cells().filter(|c| c.x < 0).count();
+        }
+
+        assert!(2 * right > 3 * left);
+    }
+
+    #[test]
+    fn sinks_absorb_walkers() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let sink =
+            Shape::Box(Bbox::new(Vec3::new(10, -100, -100)).expand(Vec3::new(100, 100, 100)));
+        let mut dla = DlaBuilder::new().sink(sink.clone()).build().unwrap();
+
+        for _ in 0..100 {
+            let walker = dla.spawn_walker(&mut rng).unwrap();
+            assert!(!sink.contains(walker.position()));
+        }
+        for _ in 0..100 {
+            dla.add(&mut rng);
+        }
+
+        assert!(dla.stats().absorbed > 0);
+        assert!(dla.cells().all(|c| c.x < 10));
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot add a particle to the DLA: cannot find a free cell")]
+    fn adding_panics_without_free_cells() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.add_container(Shape::Sphere {
+            center: Vec3::new(100, 0, 0),
+            radius: 5,
+        });
+        dla.add(&mut rng);
+    }
+
+    #[test]
+    fn spawning_gives_up_without_free_cells() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        // unlike the builder, the plain constructor doesn't check free space
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.add_container(Shape::Sphere {
+            center: Vec3::new(100, 0, 0),
+            radius: 5,
+        });
+
+        assert_eq!(
+            dla.add_with(&mut rng, &mut |_: &Event| {}),
+            Err(DlaError::NoFreeSpawnCell)
+        );
+        assert_eq!(
+            dla.run_until(&mut rng, &[StopCondition::Particles(10)]),
+            Err(DlaError::NoFreeSpawnCell)
+        );
+        assert_eq!(dla.len(), 1);
+    }
+
+    #[test]
+    fn grows_at_the_edge_of_the_bounds() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let corner = Vec3::new(MAX_COORD, -MAX_COORD, MAX_COORD);
+
+        let mut dla = DlaBuilder::new()
+            .seeds(vec![corner])
+            .spawn_radius(20)
+            .attraction_radius(4)
+            .build()
+            .unwrap();
+        for _ in 0..50 {
+            dla.add(&mut rng);
+        }
+        assert!(dla.len() > 1);
+        assert!(dla.cells().all(|c| c.is_bounded()));
+        assert!(dla.bbox().volume() > 0);
+
+        let dla = DlaBuilder::new()
+            .seeds(vec![corner])
+            .spawn_radius(u32::MAX)
+            .build()
+            .unwrap();
+        for _ in 0..100 {
+            assert!(dla.spawn_walker(&mut rng).unwrap().position().is_bounded());
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_roundtrip() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+        dla.set_species(vec![Species::new(8), Species::new(6)]);
+        dla.set_domain(Domain::periodic(Vec3::new(-50, -50, -50), 100).unwrap());
+        for _ in 0..50 {
+            dla.add(&mut rng);
+        }
+
+        let json = serde_json::to_string(&dla).unwrap();
+        let de: Dla = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(de.octree(), dla.octree());
+        assert_eq!(de.bbox(), dla.bbox());
+        assert_eq!(de.domain(), dla.domain());
+        assert_eq!(de.species(), dla.species());
+        assert!(dla.cells().all(|c| de.species_of(*c) == dla.species_of(*c)));
+
+        // invalid DLAs are rejected like the builder would
+        let valid = serde_json::to_value(&dla).unwrap();
+        let invalid = |field: &str, value: serde_json::Value| {
+            let mut data = valid.clone();
+            data[field] = value;
+            serde_json::from_value::<Dla>(data).unwrap_err().to_string()
+        };
+
+        assert_eq!(
+            invalid("cells", serde_json::json!({ "points": [] })),
+            DlaError::NoSeeds.to_string()
+        );
+        assert_eq!(
+            invalid("attraction_radius", serde_json::json!(2)),
+            DlaError::AttractionRadiusTooSmall { radius: 2, min: 4 }.to_string()
+        );
+        assert_eq!(
+            invalid("stickiness", serde_json::json!(1.5)),
+            DlaError::InvalidStickiness(1.5).to_string()
+        );
+        assert_eq!(
+            invalid(
+                "cell_species",
+                serde_json::json!([[{ "x": 0, "y": 0, "z": 0 }, 2]])
+            ),
+            "cell (0,0,0) belongs to unknown species 2"
+        );
+        assert_eq!(
+            invalid(
+                "cell_species",
+                serde_json::json!([[{ "x": 1000, "y": 0, "z": 0 }, 1]])
+            ),
+            "cell (1000,0,0) has a species but it's not part of the DLA"
+        );
+    }
+}
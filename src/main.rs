@@ -1,48 +1,338 @@
 use std::{
-    collections::HashSet,
-    fs::File,
-    io::{self, BufWriter, Write},
+    collections::{HashMap, HashSet},
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Seek, SeekFrom, Write},
     path::{Path, PathBuf},
     time,
 };
 
-use clap::Parser;
-
-use dla::{Dla, Vec3};
+use clap::{Args, Parser, Subcommand};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use dla::{
+    anisotropy::{Anisotropy, Symmetry},
+    dlca::Dlca,
+    dump,
+    geo::{Axis, Bbox, Ray, Sphere, Transform, Vec3f},
+    growth::Connectivity,
+    shape::{Mesh, Shape},
+    species::{Species, SpeciesId},
+    Attractor, Dla, DlaBuilder, Domain, Event, GrowthModel, Observer, StopCondition, Vec3,
+};
 
-/// Simulate 3D diffusion limited aggregation (DLA for short) and save the final
-/// system as a scene ready to be rendered using povray for example.
+/// Simulate 3D diffusion limited aggregation (DLA for short), save the final
+/// system as a scene ready to be rendered using povray for example and inspect
+/// saved systems.
 #[derive(Parser, Debug)]
 struct App {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Simulate a DLA and save it as a native dump that the other subcommands
+    /// can load, optionally alongside other scene formats.
+    Simulate(Box<SimulateArgs>),
+
+    /// Convert a saved DLA to other scene formats.
+    Convert(ConvertArgs),
+
+    /// Render a saved DLA to a PPM image.
+    Render(RenderArgs),
+
+    /// Print statistics about a saved DLA.
+    Analyze(AnalyzeArgs),
+
+    /// Combine saved aggregates into a single scene.
+    Merge(MergeArgs),
+}
+
+#[derive(Args, Debug)]
+struct SimulateArgs {
+    /// The growth model to simulate: `dla` for diffusion limited aggregation
+    /// around a seed, `ballistic` for ballistic aggregation, `eden` for Eden
+    /// growth, `lattice` for strict on-lattice DLA or `dlca` for diffusion
+    /// limited cluster-cluster aggregation in a periodic box. The `dlca`
+    /// model doesn't support the stop conditions other than `--timeout` nor
+    /// species, anisotropy and shapes.
+    #[clap(short = 'm', long = "model", default_value = "dla")]
+    model: Model,
+
+    /// Which lattice sites are neighbors in the `lattice` model: 6 for sites
+    /// sharing a face, 18 to also include edges and 26 to also include
+    /// corners. Defaults to 6.
+    #[clap(long = "connectivity", value_parser = parse_connectivity)]
+    connectivity: Option<Connectivity>,
+
+    /// Size of the periodic box used by the `dlca` model and by `--periodic`.
+    #[clap(long = "box-size", default_value = "100")]
+    box_size: u32,
+
+    /// Grow the DLA in a periodic box of size `--box-size` centered on the
+    /// seed instead of in unbounded space.
+    #[clap(long = "periodic")]
+    periodic: bool,
+
     /// Number of particles to add to the DLA system.
     #[clap(short = 'p', long = "particles", default_value = "10000")]
     particles: usize,
 
+    /// Stop as soon as the largest dimension of the bounding box of the DLA
+    /// reaches this extent.
+    #[clap(long = "max-extent")]
+    max_extent: Option<i64>,
+
+    /// Stop as soon as the DLA touches the given plane, specified as
+    /// `<axis>=<value>`, for example `y=100`.
+    #[clap(long = "plane", value_parser = parse_plane)]
+    plane: Option<StopCondition>,
+
+    /// Stop as soon as a particle is at least this far from the origin.
+    #[clap(long = "sphere")]
+    sphere: Option<i64>,
+
+    /// Stop after the given number of seconds.
+    #[clap(long = "timeout")]
+    timeout: Option<f64>,
+
+    /// Stop as soon as the estimated fractal dimension of the DLA reaches the
+    /// given value.
+    #[clap(long = "target-dimension")]
+    target_dimension: Option<f64>,
+
     /// The attraction radius of each particle that makes other particles stick
     /// to it.
     #[clap(short = 'a', long = "attraction-radius", default_value = "8")]
     attraction_radius: u16,
 
     /// How far away new particles are generated from the core of the current
-    /// DLA.
-    #[clap(short = 'g', long = "spawn-radius", default_value = "10")]
-    spawn_radius: u32,
+    /// DLA. Defaults to 10.
+    #[clap(short = 'g', long = "spawn-radius")]
+    spawn_radius: Option<u32>,
+
+    /// Add a species of particles, specified as
+    /// `<attraction-radius>:<spawn-ratio>:<stickiness-0>,<stickiness-1>,...`
+    /// where `stickiness-i` is the probability of sticking to a particle of
+    /// the i-th species. Seeds belong to the first species.
+    #[clap(long = "species", value_parser = parse_species)]
+    species: Vec<Species>,
+
+    /// Add an obstacle walkers cannot enter. Supported shapes are
+    /// `box:<x0>,<y0>,<z0>,<x1>,<y1>,<z1>`, `sphere:<x>,<y>,<z>,<r>`,
+    /// `cylinder:<axis>,<x>,<y>,<z>,<r>,<h>` and `obj:<path>`.
+    #[clap(long = "obstacle", value_parser = parse_shape)]
+    obstacles: Vec<Shape>,
+
+    /// Add a container walkers cannot leave, it accepts the same shapes as
+    /// `--obstacle`.
+    #[clap(long = "container", value_parser = parse_shape)]
+    containers: Vec<Shape>,
+
+    /// Add an attractor that pulls walkers towards itself and makes particles
+    /// close to it stickier, it accepts the same shapes as `--obstacle`.
+    #[clap(long = "attractor", value_parser = parse_shape)]
+    attractors: Vec<Shape>,
+
+    /// Probability that a walker moves towards the closest attractor along
+    /// each axis, only the `dla` model supports it. Defaults to 0.2 with the
+    /// `dla` model and to 0 otherwise.
+    #[clap(long = "attractor-pull")]
+    attractor_pull: Option<f64>,
+
+    /// Probability that a particle sticks to the DLA when it's close to an
+    /// attractor. Defaults to 1.
+    #[clap(long = "attractor-stickiness")]
+    attractor_stickiness: Option<f64>,
+
+    /// How close to an attractor a particle must be to use the attractor
+    /// stickiness. Defaults to 10.
+    #[clap(long = "attractor-range")]
+    attractor_range: Option<f64>,
+
+    /// Add a sink that absorbs the walkers that enter it, it accepts the same
+    /// shapes as `--obstacle`.
+    #[clap(long = "sink", value_parser = parse_shape)]
+    sinks: Vec<Shape>,
+
+    /// Probability that a walker sticks to the DLA once it's close enough.
+    /// Defaults to 1.
+    #[clap(long = "stickiness")]
+    stickiness: Option<f64>,
+
+    /// Make particles stickier along the preferred directions of the given
+    /// lattice symmetry, either `cubic` or `hexagonal`.
+    #[clap(long = "anisotropy", value_parser = parse_symmetry)]
+    anisotropy: Option<Symmetry>,
+
+    /// How much the attachment direction matters when `--anisotropy` is
+    /// given, between 0 and 1. Defaults to 0.9.
+    #[clap(long = "anisotropy-strength")]
+    anisotropy_strength: Option<f64>,
+
+    /// How many times a site must be hit before it's occupied when
+    /// `--anisotropy` is given, higher values reduce the noise. Defaults to
+    /// 1.
+    #[clap(long = "hits")]
+    hits: Option<u32>,
+
+    /// The output formats the scene should be saved as besides the native
+    /// dump.
+    /// As of now `javascript`, `povray`, `csv` and `ply` are supported.
+    #[clap(short = 's', long = "scene-format")]
+    scene_formats: Vec<SceneFormat>,
 
-    /// The output formats the scene should be saved as. As of now `javascript,
-    /// `povray` and `csv` are supported.
+    /// Seed of the random number generator, a random one is picked if not
+    /// given.
+    #[clap(long = "seed")]
+    seed: Option<u64>,
+
+    /// Collect per particle statistics about the random walks and print them
+    /// in the summary. It slows down the simulation a bit.
+    #[clap(long = "stats")]
+    stats: bool,
+
+    /// Save the bounding boxes of the nodes of the octree holding the cells as
+    /// a wireframe, either as an `obj` file or as a `povray` scene.
+    #[clap(long = "octree-wireframe")]
+    octree_wireframes: Vec<WireframeFormat>,
+
+    /// Also store in the native dump the index of the particle each particle
+    /// stuck to. It needs to keep track of the index of every particle.
+    #[clap(long = "parents")]
+    parents: bool,
+
+    /// Append the particles to the CSV and PLY scenes as soon as they're
+    /// attached instead of saving them once the simulation is over. The DLCA
+    /// model always saves them at the end since its clusters keep moving.
+    #[clap(long = "stream")]
+    stream: bool,
+
+    /// Save a JSON report describing the simulation to the given file.
+    #[clap(long = "report")]
+    report: Option<PathBuf>,
+
+    /// Output filename where to save the scene, the extension is replaced by
+    /// the one of each format.
+    #[clap(default_value = "dla")]
+    output: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct ConvertArgs {
+    /// The saved DLA to convert, either a native dump or a CSV scene.
+    input: PathBuf,
+
+    /// The output formats the scene should be saved as, it accepts the same
+    /// formats as `simulate`.
     #[clap(short = 's', long = "scene-format", default_value = "povray")]
     scene_formats: Vec<SceneFormat>,
 
-    /// Output filename where to save the scene.
-    #[clap(default_value = "dla.pov")]
+    /// Output filename where to save the scene, defaults to the input one.
+    #[clap(short = 'o', long = "output")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct RenderArgs {
+    /// The saved DLA to render, either a native dump or a CSV scene.
+    input: PathBuf,
+
+    /// Width of the image in pixels.
+    #[clap(long = "width", default_value = "1024")]
+    width: u32,
+
+    /// Height of the image in pixels.
+    #[clap(long = "height", default_value = "1024")]
+    height: u32,
+
+    /// Rotation of the camera around the vertical axis, in degrees.
+    #[clap(long = "yaw", default_value = "30")]
+    yaw: f64,
+
+    /// Rotation of the camera around the horizontal axis, in degrees.
+    #[clap(long = "pitch", default_value = "20")]
+    pitch: f64,
+
+    /// Output filename where to save the image, defaults to the input one
+    /// with the `ppm` extension.
+    #[clap(short = 'o', long = "output")]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct AnalyzeArgs {
+    /// The saved DLA to analyze, either a native dump or a CSV scene.
+    input: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct MergeArgs {
+    /// The saved aggregates to merge, either native dumps or CSV scenes.
+    #[clap(required = true)]
+    inputs: Vec<PathBuf>,
+
+    /// Move the aggregate of the input with the same index by the given
+    /// offset, specified as `<x>,<y>,<z>`. There cannot be more offsets than
+    /// inputs.
+    #[clap(long = "offset", value_parser = parse_vec3)]
+    offsets: Vec<Vec3>,
+
+    /// The output formats the merged scene should be saved as, it accepts
+    /// the same formats as the simulation.
+    #[clap(short = 's', long = "scene-format", default_value = "povray")]
+    scene_formats: Vec<SceneFormat>,
+
+    /// Output filename where to save the merged scene.
+    #[clap(short = 'o', long = "output", default_value = "merged.pov")]
     output: PathBuf,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Model {
+    Dla,
+    Ballistic,
+    Eden,
+    Lattice,
+    Dlca,
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 enum SceneFormat {
     Povray,
     Js,
     Csv,
+    Ply,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum WireframeFormat {
+    Obj,
+    Povray,
+}
+
+/// Writes the cells of a DLA one at a time to the scene formats that don't
+/// need to know the whole DLA upfront, see `CellWriter::supports`.
+#[derive(Debug)]
+struct CellWriter {
+    format: SceneFormat,
+    path: PathBuf,
+    out: BufWriter<File>,
+    has_species: bool,
+    len: u64,
+}
+
+/// Records the particles of a simulation in its dump and in the streamed
+/// scenes as soon as they're attached. Observers can't fail, so the first
+/// write error interrupts the run and is kept to be reported at the end.
+struct Recorder<'a> {
+    dump: dump::Writer<BufWriter<File>>,
+    streams: &'a mut [CellWriter],
+    parents: bool,
+    indices: HashMap<Vec3, u64>,
+    particles: usize,
+    attached: usize,
+    error: Option<io::Error>,
 }
 
 #[derive(Debug)]
@@ -64,27 +354,99 @@ struct Light {
     intensity: f32,
 }
 
-fn main() -> io::Result<()> {
-    let args = App::parse();
+// the tuning options are optional only so that the models ignoring them can
+// reject them, these are their defaults
+impl SimulateArgs {
+    fn connectivity(&self) -> Connectivity {
+        self.connectivity.unwrap_or(Connectivity::Six)
+    }
 
-    let seeds = vec![Vec3::new(0, 0, 0)];
-    let mut dla = Dla::new(args.spawn_radius, args.attraction_radius, seeds).unwrap();
+    fn spawn_radius(&self) -> u32 {
+        self.spawn_radius.unwrap_or(10)
+    }
 
-    let start = time::Instant::now();
-    let mut rng = rand::thread_rng();
-    for i in 0..args.particles {
-        if i % 100 == 0 {
-            print!(
-                "\rgenerated {} particles, progress: {}%",
-                i,
-                i * 100 / args.particles
-            );
-            io::stdout().flush()?;
-        }
+    fn stickiness(&self) -> f64 {
+        self.stickiness.unwrap_or(1.0)
+    }
 
-        dla.add(&mut rng);
+    fn anisotropy_strength(&self) -> f64 {
+        self.anisotropy_strength.unwrap_or(0.9)
     }
 
+    fn hits(&self) -> u32 {
+        self.hits.unwrap_or(1)
+    }
+
+    fn attractor_pull(&self) -> f64 {
+        let default = if self.model == Model::Dla { 0.2 } else { 0.0 };
+        self.attractor_pull.unwrap_or(default)
+    }
+
+    fn attractor_stickiness(&self) -> f64 {
+        self.attractor_stickiness.unwrap_or(1.0)
+    }
+
+    fn attractor_range(&self) -> f64 {
+        self.attractor_range.unwrap_or(10.0)
+    }
+}
+
+fn main() {
+    let App { command } = App::parse();
+
+    let res = match command {
+        Command::Simulate(args) => simulate(&args),
+        Command::Convert(args) => convert(&args),
+        Command::Render(args) => render(&args),
+        Command::Analyze(args) => analyze(&args),
+        Command::Merge(args) => merge(&args),
+    };
+
+    if let Err(e) = res {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+fn simulate(args: &SimulateArgs) -> io::Result<()> {
+    let seed = args.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let mut streams = vec![];
+    if args.stream && args.model != Model::Dlca {
+        for f in args.scene_formats.iter().collect::<HashSet<_>>() {
+            if CellWriter::supports(*f) {
+                streams.push(CellWriter::create(
+                    &args.output,
+                    *f,
+                    args.species.len() > 1,
+                )?);
+            }
+        }
+    }
+
+    let start = time::Instant::now();
+    let (dla, stop_reason) = match args.model {
+        Model::Dla => simulate_dla(args, GrowthModel::Diffusion, seed, &mut streams, &mut rng)?,
+        Model::Ballistic => {
+            simulate_dla(args, GrowthModel::Ballistic, seed, &mut streams, &mut rng)?
+        }
+        Model::Eden => simulate_dla(args, GrowthModel::Eden, seed, &mut streams, &mut rng)?,
+        Model::Lattice => simulate_dla(
+            args,
+            GrowthModel::Lattice(args.connectivity()),
+            seed,
+            &mut streams,
+            &mut rng,
+        )?,
+        Model::Dlca => {
+            let (dla, stop_reason) = simulate_dlca(args, &mut rng)?;
+            let f = File::create(dump_path(&args.output))?;
+            dump::save(&dla, BufWriter::new(f), Some(seed))?;
+            (dla, stop_reason)
+        }
+    };
+
     // clear current line, let's try to avoid another dep for this single line of code
     println!("\r\x1B[2K");
 
@@ -94,31 +456,793 @@ fn main() -> io::Result<()> {
     println!(
         r#"# DLA
 
-The DLA system was correctly generated in {}m {}s.
+The DLA system was correctly generated in {}m {}s, {}.
 
 It contains {} particles and its bounding box goes from
 ({},{},{}) to ({},{},{}) with a total volume of {}.
 "#,
         duration.as_secs() / 60, duration.as_secs() % 60,
+        stop_reason,
         dla.len(),
         dla.bbox().lower().x, dla.bbox().lower().y, dla.bbox().lower().z,
         dla.bbox().upper().x, dla.bbox().upper().y, dla.bbox().upper().z,
         dla.bbox().volume(),
     );
 
-    let scene = Scene::new(dla);
+    let stats = dla.stats();
+    if args.model != Model::Dlca {
+        #[rustfmt::skip]
+        println!(
+            r#"The walkers took {} steps in total and were respawned {} times, {} of
+which after being absorbed by a sink. {} walkers stuck to an occupied site and
+merged with its cell.
+"#,
+            stats.steps, stats.respawns, stats.absorbed, stats.merged,
+        );
+    }
+
+    // the averages only account for the particles whose statistics were
+    // collected, which aren't necessarily all of them
+    let particles = stats.particle_steps().zip(stats.particle_respawns());
+    if let Some(((avg_steps, max_steps), (avg_respawns, max_respawns))) =
+        particles.filter(|_| args.stats)
+    {
+        #[rustfmt::skip]
+        println!(
+            r#"## Walkers
+
+| | average | max |
+|-|-|-|
+| steps | {:.1} | {} |
+| respawns | {:.1} | {} |
+
+{:.3}s were spent looking for the nearest cell.
+"#,
+            avg_steps, max_steps,
+            avg_respawns, max_respawns,
+            stats.nearest_time.as_secs_f64(),
+        );
+    }
+
+    if args.stats {
+        print_octree_stats(&dla);
+    }
+
+    println!(
+        r#"## Native Dump
+
+The DLA has been saved as a native dump ({}) that the other subcommands can load.
+"#,
+        dump_path(&args.output).display()
+    );
+
+    let mut outputs = vec![dump_path(&args.output)];
+    for f in args.octree_wireframes.iter().collect::<HashSet<_>>() {
+        let path = match f {
+            WireframeFormat::Obj => save_octree_obj(&args.output, &dla)?,
+            WireframeFormat::Povray => save_octree_pov(&args.output, &dla)?,
+        };
+        outputs.push(path);
+    }
+
+    let scene = Scene::new(dla);
+
+    // the streamed scenes already contain all the particles
+    let scene_formats = args
+        .scene_formats
+        .iter()
+        .copied()
+        .filter(|f| streams.iter().all(|s| s.format != *f))
+        .collect::<Vec<_>>();
+    for s in streams {
+        outputs.push(s.finish()?);
+    }
+    outputs.extend(save_scene(&args.output, &scene_formats, &scene)?);
+
+    if let Some(report) = &args.report {
+        Report::new(args, seed, &stop_reason, duration, &scene.dla, &outputs).save(report)?;
+    }
+
+    Ok(())
+}
+
+fn convert(args: &ConvertArgs) -> io::Result<()> {
+    let (_, dla) = load_dla(&args.input)?;
+    let output = args.output.as_ref().unwrap_or(&args.input);
+
+    save_scene(output, &args.scene_formats, &Scene::new(dla))?;
+
+    Ok(())
+}
+
+fn render(args: &RenderArgs) -> io::Result<()> {
+    let (_, dla) = load_dla(&args.input)?;
+    let output = args
+        .output
+        .clone()
+        .unwrap_or_else(|| args.input.with_extension("ppm"));
+
+    save_ppm_image(&output, &dla, args)?;
+
+    Ok(())
+}
+
+fn analyze(args: &AnalyzeArgs) -> io::Result<()> {
+    let (header, dla) = load_dla(&args.input)?;
+    let bbox = dla.bbox();
+
+    #[rustfmt::skip]
+    println!(
+        r#"# Analysis
+
+The DLA saved in {} contains {} particles and its bounding box goes from
+({},{},{}) to ({},{},{}) with a total volume of {}.
+
+Its radius of gyration is {:.2} and its estimated fractal dimension is {}.
+"#,
+        args.input.display(),
+        dla.len(),
+        bbox.lower().x, bbox.lower().y, bbox.lower().z,
+        bbox.upper().x, bbox.upper().y, bbox.upper().z,
+        bbox.volume(),
+        dla.radius_of_gyration(),
+        dla.fractal_dimension()
+            .map_or_else(|| "unknown".to_string(), |d| format!("{:.3}", d)),
+    );
+
+    if let Some(header) = header {
+        #[rustfmt::skip]
+        println!(
+            r#"It was grown by the `{}` model with an attraction radius of {} and a spawn
+radius of {}{}.
+"#,
+            Model::of(header.growth_model).name(),
+            header.attraction_radius,
+            header.spawn_radius,
+            header
+                .seed
+                .map_or_else(String::new, |s| format!(" using the seed {}", s)),
+        );
+    }
+
+    let counts = species_counts(&dla);
+    if counts.len() > 1 {
+        println!("## Species\n\n| species | particles |\n|-|-|");
+        for (species, count) in counts.iter().enumerate() {
+            println!("| {} | {} |", species, count);
+        }
+        println!();
+    }
+
+    print_octree_stats(&dla);
+
+    Ok(())
+}
+
+/// The number of cells of each species, there's an entry for every species
+/// of the DLA and for any species id its cells use beyond those.
+fn species_counts(dla: &Dla) -> Vec<usize> {
+    let mut counts = vec![0; dla.species().len()];
+    for c in dla.cells() {
+        let species = dla.species_of(*c);
+        if species >= counts.len() {
+            counts.resize(species + 1, 0);
+        }
+        counts[species] += 1;
+    }
+
+    counts
+}
+
+fn print_octree_stats(dla: &Dla) {
+    let octree = dla.octree().stats();
+    let occupied = octree.leaves - octree.empty_leaves;
+    let average_occupancy = if occupied == 0 {
+        0.0
+    } else {
+        dla.len() as f64 / occupied as f64
+    };
+
+    #[rustfmt::skip]
+    println!(
+        r#"## Octree
+
+The octree has {} branches and {} leaves, {} of which are empty, with {:.1}
+points per non empty leaf on average. Its root grew {} times.
+
+| depth | leaves |
+|-|-|"#,
+        octree.branches, octree.leaves, octree.empty_leaves,
+        average_occupancy, octree.rebuilt_count,
+    );
+    for (depth, leaves) in octree.depth_histogram.iter().enumerate() {
+        println!("| {} | {} |", depth, leaves);
+    }
+    println!();
+}
+
+fn merge(args: &MergeArgs) -> io::Result<()> {
+    if args.offsets.len() > args.inputs.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "{} offsets were given for only {} inputs",
+                args.offsets.len(),
+                args.inputs.len()
+            ),
+        ));
+    }
+
+    let mut dlas = vec![];
+    for (i, input) in args.inputs.iter().enumerate() {
+        let (_, mut dla) = load_dla(input)?;
+        if let Some(offset) = args.offsets.get(i) {
+            dla.translate(*offset).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("cannot move {}: {}", input.display(), e),
+                )
+            })?;
+        }
+        dlas.push(dla);
+    }
+
+    let dla = Dla::merge_all(dlas).expect("at least an input is required");
+
+    #[rustfmt::skip]
+    println!(
+        r#"# DLA
+
+{} aggregates were merged into a single one that contains {} particles.
+"#,
+        args.inputs.len(), dla.len(),
+    );
+
+    save_scene(&args.output, &args.scene_formats, &Scene::new(dla))?;
+
+    Ok(())
+}
+
+/// Save the scene in all the given formats, return the paths of the saved
+/// files.
+fn save_scene(
+    output: &Path,
+    scene_formats: &[SceneFormat],
+    scene: &Scene,
+) -> io::Result<Vec<PathBuf>> {
+    let mut outputs = vec![];
+
+    for r in scene_formats.iter().collect::<HashSet<_>>() {
+        let path = match r {
+            SceneFormat::Povray => save_pov_scene(output, scene)?,
+            SceneFormat::Js => save_js_scene(output, scene)?,
+            SceneFormat::Csv => save_csv_scene(output, scene)?,
+            SceneFormat::Ply => save_ply_scene(output, scene)?,
+        };
+        outputs.push(path);
+    }
+
+    Ok(outputs)
+}
+
+/// Path of the native dump saved next to the given output.
+fn dump_path(output: &Path) -> PathBuf {
+    output.with_extension("dla")
+}
+
+/// Load a DLA saved either as a CSV scene or as a native dump, depending on
+/// its extension. The header is only available for native dumps.
+fn load_dla(path: &Path) -> io::Result<(Option<dump::Header>, Dla)> {
+    if path.extension().is_some_and(|e| e == "csv") {
+        return Ok((None, load_csv_scene(path)?));
+    }
+
+    let f = File::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot open {}: {}", path.display(), e)))?;
+    let (header, dla) = dump::load(io::BufReader::new(f))
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+
+    Ok((Some(header), dla))
+}
+
+/// Load a DLA saved as a CSV scene, see `save_csv_scene`.
+fn load_csv_scene(path: &Path) -> io::Result<Dla> {
+    let invalid = |line: usize, msg: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}:{}: {}", path.display(), line + 1, msg),
+        )
+    };
+
+    let f = File::open(path)
+        .map_err(|e| io::Error::new(e.kind(), format!("cannot open {}: {}", path.display(), e)))?;
+
+    let mut cells = vec![];
+    for (i, line) in io::BufRead::lines(io::BufReader::new(f)).enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let coords = line
+            .split(',')
+            .map(|c| {
+                c.trim()
+                    .parse::<i64>()
+                    .map_err(|e| invalid(i, format!("`{}` is not a valid coordinate: {}", c, e)))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let cell = match *coords.as_slice() {
+            [x, y, z] => (Vec3::new(x, y, z), 0),
+            [x, y, z, species] if species >= 0 => (Vec3::new(x, y, z), species as usize),
+            _ => {
+                return Err(invalid(
+                    i,
+                    format!("expected `<x>,<y>,<z>[,<species>]`, got `{}`", line),
+                ))
+            }
+        };
+        cells.push(cell);
+    }
+
+    // the parameters don't matter since merged aggregates don't grow
+    Dla::from_cells(10, 8, cells).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })
+}
+
+fn simulate_dla(
+    args: &SimulateArgs,
+    model: GrowthModel,
+    seed: u64,
+    streams: &mut [CellWriter],
+    rng: &mut StdRng,
+) -> io::Result<(Dla, String)> {
+    let mut builder = DlaBuilder::new()
+        .spawn_radius(args.spawn_radius())
+        .attraction_radius(args.attraction_radius)
+        .growth_model(model)
+        .collect_stats(args.stats)
+        .stickiness(args.stickiness())
+        .anisotropy(args.anisotropy.map(|symmetry| Anisotropy {
+            symmetry,
+            strength: args.anisotropy_strength(),
+            hits: args.hits(),
+        }))
+        .species(args.species.clone());
+    if args.periodic {
+        let size = i64::from(args.box_size);
+        let domain = Domain::periodic(Vec3::new(-size / 2, -size / 2, -size / 2), size)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("invalid periodic box size {}", args.box_size),
+                )
+            })?;
+        builder = builder.domain(domain);
+    }
+    for o in &args.obstacles {
+        builder = builder.obstacle(o.clone());
+    }
+    for c in &args.containers {
+        builder = builder.container(c.clone());
+    }
+    for a in &args.attractors {
+        builder = builder.attractor(Attractor {
+            shape: a.clone(),
+            pull: args.attractor_pull(),
+            stickiness: args.attractor_stickiness(),
+            range: args.attractor_range(),
+        });
+    }
+    for s in &args.sinks {
+        builder = builder.sink(s.clone());
+    }
+    let mut dla = builder
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    // particles are streamed to the dump as soon as they're attached, its
+    // length and bounding box are patched in the header at the end
+    let header = dump::Header {
+        len: None,
+        bbox: None,
+        parents: args.parents,
+        ..dump::Header::of(&dla, Some(seed))
+    };
+    let dump = dump::Writer::new(
+        BufWriter::new(File::create(dump_path(&args.output))?),
+        header,
+    )?;
+    let mut recorder = Recorder {
+        dump,
+        streams,
+        parents: args.parents,
+        indices: HashMap::new(),
+        particles: args.particles,
+        attached: 0,
+        error: None,
+    };
+    for &cell in dla.cells() {
+        recorder.record(cell, dla.species_of(cell), None)?;
+    }
+
+    let stop_conditions = stop_conditions(args, dla.len());
+    let stop_reason = dla
+        .run_until_with(rng, &stop_conditions, &mut recorder)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    if let Some(e) = recorder.error {
+        return Err(e);
+    }
+    let stop_reason = stop_reason.expect("the particles stop condition is always present");
+    let stop_reason = describe_stop_condition(stop_reason);
+
+    recorder.dump.finish_patched()?;
+
+    Ok((dla, stop_reason))
+}
+
+fn simulate_dlca(args: &SimulateArgs, rng: &mut StdRng) -> io::Result<(Dla, String)> {
+    // the clusters just diffuse in the box until they all merge, the options
+    // shaping the growth of a single DLA would be silently ignored
+    let unsupported = [
+        ("--max-extent", args.max_extent.is_some()),
+        ("--plane", args.plane.is_some()),
+        ("--sphere", args.sphere.is_some()),
+        ("--target-dimension", args.target_dimension.is_some()),
+        ("--species", !args.species.is_empty()),
+        ("--obstacle", !args.obstacles.is_empty()),
+        ("--container", !args.containers.is_empty()),
+        ("--attractor", !args.attractors.is_empty()),
+        ("--sink", !args.sinks.is_empty()),
+        ("--anisotropy", args.anisotropy.is_some()),
+        ("--parents", args.parents),
+        ("--spawn-radius", args.spawn_radius.is_some()),
+        ("--connectivity", args.connectivity.is_some()),
+        ("--stickiness", args.stickiness.is_some()),
+        ("--anisotropy-strength", args.anisotropy_strength.is_some()),
+        ("--hits", args.hits.is_some()),
+        ("--attractor-pull", args.attractor_pull.is_some()),
+        (
+            "--attractor-stickiness",
+            args.attractor_stickiness.is_some(),
+        ),
+        ("--attractor-range", args.attractor_range.is_some()),
+    ];
+    if let Some((name, _)) = unsupported.iter().find(|(_, given)| *given) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not supported by the dlca model", name),
+        ));
+    }
+
+    let mut dlca = Dlca::new(args.box_size, args.particles, rng).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "cannot simulate {} particles in a box of size {}",
+                args.particles, args.box_size
+            ),
+        )
+    })?;
+
+    let start = time::Instant::now();
+    let timeout = args.timeout.map(time::Duration::from_secs_f64);
+
+    let stop_reason = loop {
+        if dlca.is_done() {
+            break "after merging all the clusters".to_string();
+        }
+
+        if timeout.is_some_and(|t| start.elapsed() >= t) {
+            break format!(
+                "after timing out with {} clusters left",
+                dlca.clusters().len()
+            );
+        }
+
+        if dlca.step(rng) {
+            print!("\r\x1B[2K{} clusters left", dlca.clusters().len());
+            let _ = io::stdout().flush();
+        }
+    };
+
+    let dla = dlca
+        .to_dla(args.attraction_radius)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    Ok((dla, stop_reason))
+}
+
+fn stop_conditions(args: &SimulateArgs, initial_len: usize) -> Vec<StopCondition> {
+    let mut conditions = vec![StopCondition::Particles(initial_len + args.particles)];
+
+    if let Some(e) = args.max_extent {
+        conditions.push(StopCondition::MaxExtent(e));
+    }
+    if let Some(p) = &args.plane {
+        conditions.push(p.clone());
+    }
+    if let Some(radius) = args.sphere {
+        conditions.push(StopCondition::Sphere {
+            center: Vec3::new(0, 0, 0),
+            radius,
+        });
+    }
+    if let Some(t) = args.timeout {
+        conditions.push(StopCondition::Timeout(time::Duration::from_secs_f64(t)));
+    }
+    if let Some(d) = args.target_dimension {
+        conditions.push(StopCondition::FractalDimension(d));
+    }
+
+    conditions
+}
+
+fn describe_stop_condition(c: &StopCondition) -> String {
+    match c {
+        StopCondition::Particles(n) => format!("after reaching {} particles", n),
+        StopCondition::MaxExtent(e) => format!("after reaching an extent of {}", e),
+        StopCondition::Plane { axis, value } => {
+            format!("after touching the plane {:?}={}", axis, value)
+        }
+        StopCondition::Sphere { radius, .. } => {
+            format!("after touching the sphere of radius {}", radius)
+        }
+        StopCondition::Timeout(t) => format!("after timing out at {}s", t.as_secs_f64()),
+        StopCondition::FractalDimension(d) => {
+            format!("after reaching a fractal dimension of {}", d)
+        }
+    }
+}
+
+fn parse_shape(s: &str) -> Result<Shape, String> {
+    let (kind, params) = s
+        .split_once(':')
+        .ok_or_else(|| format!("`{}` is not a valid shape, expected `<shape>:<params>`", s))?;
+
+    if kind == "obj" {
+        let f = File::open(params).map_err(|e| format!("cannot open `{}`: {}", params, e))?;
+        let mesh = Mesh::from_obj(io::BufReader::new(f))
+            .map_err(|e| format!("cannot load `{}`: {}", params, e))?;
+        return Ok(Shape::Mesh(mesh));
+    }
+
+    let mut params = params.split(',').map(str::trim);
+
+    let axis = if kind == "cylinder" {
+        Some(parse_axis(params.next().unwrap_or_default())?)
+    } else {
+        None
+    };
+
+    let params = params
+        .map(|p| {
+            p.parse::<i64>()
+                .map_err(|e| format!("`{}` is not a valid shape parameter: {}", p, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let shape = match (kind, axis, params.as_slice()) {
+        ("box", _, &[x0, y0, z0, x1, y1, z1]) => Ok(Shape::Box(
+            Bbox::new(Vec3::new(x0, y0, z0)).expand(Vec3::new(x1, y1, z1)),
+        )),
+        ("sphere", _, &[x, y, z, radius]) => Ok(Shape::Sphere {
+            center: Vec3::new(x, y, z),
+            radius,
+        }),
+        ("cylinder", Some(axis), &[x, y, z, radius, height]) => Ok(Shape::Cylinder {
+            base: Vec3::new(x, y, z),
+            axis,
+            radius,
+            height,
+        }),
+        ("box" | "sphere" | "cylinder", _, _) => {
+            Err(format!("wrong number of parameters for `{}`", kind))
+        }
+        _ => Err(format!("`{}` is not a valid shape", kind)),
+    }?;
+    shape.validate().map_err(|e| e.to_string())?;
+
+    Ok(shape)
+}
+
+fn parse_species(s: &str) -> Result<Species, String> {
+    let mut parts = s.splitn(3, ':');
+
+    let attraction_radius = parts.next().unwrap_or_default().trim();
+    let attraction_radius = attraction_radius.parse().map_err(|e| {
+        format!(
+            "`{}` is not a valid attraction radius: {}",
+            attraction_radius, e
+        )
+    })?;
+
+    let mut species = Species::new(attraction_radius);
+
+    if let Some(ratio) = parts.next() {
+        species.spawn_ratio = ratio
+            .trim()
+            .parse()
+            .map_err(|e| format!("`{}` is not a valid spawn ratio: {}", ratio, e))?;
+    }
+
+    if let Some(stickiness) = parts.next() {
+        species.stickiness = stickiness
+            .split(',')
+            .map(|p| {
+                p.trim()
+                    .parse()
+                    .map_err(|e| format!("`{}` is not a valid stickiness: {}", p, e))
+            })
+            .collect::<Result<_, _>>()?;
+    }
+
+    Ok(species)
+}
+
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let coords = s
+        .split(',')
+        .map(|c| {
+            c.trim()
+                .parse::<i64>()
+                .map_err(|e| format!("`{}` is not a valid coordinate: {}", c, e))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match *coords.as_slice() {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err(format!(
+            "`{}` is not a valid point, expected `<x>,<y>,<z>`",
+            s
+        )),
+    }
+}
+
+fn parse_axis(s: &str) -> Result<Axis, String> {
+    match s.trim() {
+        "x" | "X" => Ok(Axis::X),
+        "y" | "Y" => Ok(Axis::Y),
+        "z" | "Z" => Ok(Axis::Z),
+        a => Err(format!("`{}` is not a valid axis", a)),
+    }
+}
+
+fn parse_symmetry(s: &str) -> Result<Symmetry, String> {
+    match s.trim() {
+        "cubic" => Ok(Symmetry::Cubic),
+        "hexagonal" => Ok(Symmetry::Hexagonal),
+        sym => Err(format!("`{}` is not a valid symmetry", sym)),
+    }
+}
+
+fn parse_connectivity(s: &str) -> Result<Connectivity, String> {
+    match s.trim() {
+        "6" => Ok(Connectivity::Six),
+        "18" => Ok(Connectivity::Eighteen),
+        "26" => Ok(Connectivity::TwentySix),
+        c => Err(format!(
+            "`{}` is not a valid connectivity, expected 6, 18 or 26",
+            c
+        )),
+    }
+}
+
+fn parse_plane(s: &str) -> Result<StopCondition, String> {
+    let (axis, value) = s
+        .split_once('=')
+        .ok_or_else(|| format!("`{}` is not a valid plane, expected `<axis>=<value>`", s))?;
+
+    let axis = parse_axis(axis)?;
+
+    let value = value
+        .trim()
+        .parse()
+        .map_err(|e| format!("`{}` is not a valid plane coordinate: {}", value, e))?;
+
+    Ok(StopCondition::Plane { axis, value })
+}
+
+/// JSON summary of a simulation, see `--report`.
+#[derive(Debug, serde::Serialize)]
+struct Report {
+    parameters: ReportParameters,
+    seed: u64,
+    stop_reason: String,
+    cells: usize,
+    bbox: ReportBbox,
+    volume: i64,
+    duration_secs: f64,
+    walker_steps: u64,
+    respawns: u64,
+    absorbed: u64,
+    merged: u64,
+    nearest_time_secs: f64,
+    octree_rebuilt_count: usize,
+    octree_bytes: usize,
+    outputs: Vec<PathBuf>,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ReportParameters {
+    model: &'static str,
+    particles: usize,
+    attraction_radius: u16,
+    spawn_radius: u32,
+    scene_formats: Vec<&'static str>,
+    output: PathBuf,
+}
+
+/// The bounding box in the report, the library types only implement
+/// `Serialize` with the `serde` feature.
+#[derive(Debug, serde::Serialize)]
+struct ReportBbox {
+    lower: ReportPoint,
+    upper: ReportPoint,
+}
+
+#[derive(Debug, serde::Serialize)]
+struct ReportPoint {
+    x: i64,
+    y: i64,
+    z: i64,
+}
 
-    let scene_formats = args.scene_formats.into_iter().collect::<HashSet<_>>();
+impl From<Vec3> for ReportPoint {
+    fn from(Vec3 { x, y, z }: Vec3) -> Self {
+        ReportPoint { x, y, z }
+    }
+}
 
-    for r in scene_formats {
-        match r {
-            SceneFormat::Povray => save_pov_scene(&args.output, &scene)?,
-            SceneFormat::Js => save_js_scene(&args.output, &scene)?,
-            SceneFormat::Csv => save_csv_scene(&args.output, &scene)?,
+impl Report {
+    fn new(
+        args: &SimulateArgs,
+        seed: u64,
+        stop_reason: &str,
+        duration: time::Duration,
+        dla: &Dla,
+        outputs: &[PathBuf],
+    ) -> Self {
+        let stats = dla.stats();
+
+        Report {
+            parameters: ReportParameters {
+                model: args.model.name(),
+                particles: args.particles,
+                attraction_radius: args.attraction_radius,
+                spawn_radius: args.spawn_radius(),
+                scene_formats: args.scene_formats.iter().map(|f| f.name()).collect(),
+                output: args.output.clone(),
+            },
+            seed,
+            stop_reason: stop_reason.to_string(),
+            cells: dla.len(),
+            bbox: ReportBbox {
+                lower: dla.bbox().lower().into(),
+                upper: dla.bbox().upper().into(),
+            },
+            volume: dla.bbox().volume(),
+            duration_secs: duration.as_secs_f64(),
+            walker_steps: stats.steps,
+            respawns: stats.respawns,
+            absorbed: stats.absorbed,
+            merged: stats.merged,
+            nearest_time_secs: stats.nearest_time.as_secs_f64(),
+            octree_rebuilt_count: dla.octree().rebuilt_count(),
+            octree_bytes: dla.octree().memory_usage().bytes,
+            outputs: outputs.to_vec(),
         }
     }
 
-    Ok(())
+    fn save(&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut out, self)?;
+        writeln!(out)
+    }
 }
 
 fn save_pov_scene(
@@ -128,7 +1252,7 @@ fn save_pov_scene(
         camera,
         lights,
     }: &Scene,
-) -> io::Result<()> {
+) -> io::Result<PathBuf> {
     let path = path.with_extension("pov");
     let mut out = BufWriter::new(File::create(&path)?);
 
@@ -171,28 +1295,51 @@ camera {{
         )?;
     }
 
-    let center = bbox.center();
-    let mut cells = dla
-        .cells()
-        .map(|cc| (cc, center.dist2(*cc)))
-        .collect::<Vec<_>>();
-    cells.sort_by_key(|(_, d)| *d);
+    if dla.species().len() > 1 {
+        write_pov_species(&mut out, dla)?;
+    } else {
+        write_pov_gradients(&mut out, dla)?;
+    }
+
+    println!(
+        r#"## PovRay Scene
+
+The DLA scene has been saved as a PovRay scene ({path}) which is possible to
+render with a command like the following
+
+`povray +A +W1600 +H1600 {path}`
+"#,
+        path = path.display()
+    );
+
+    Ok(path)
+}
 
-    let max_d = cells
-        .last()
-        .expect("empty dla, cannot happen since it should be seeded")
-        .1;
-    let mut cells = cells.into_iter();
+/// Write the cells of the DLA grouped in bands by distance from the center,
+/// each with a slightly different shade of green.
+///
+/// The cells are streamed from the octree once per band instead of being
+/// sorted by distance, so that no copy of them is ever made.
+fn write_pov_gradients(out: &mut impl Write, dla: &Dla) -> io::Result<()> {
+    let center = dla.bbox().center();
+    let max_d = dla
+        .cells()
+        .map(|c| center.dist2(*c))
+        .max()
+        .expect("empty dla, cannot happen since it should be seeded");
 
     let gradients = 3;
     let n = gradients * 2;
     for i in 0..n {
+        // the first band also contains the cells right at the center
+        let (min_d, max_d) = (i * max_d / n, (i + 1) * max_d / n);
+
         writeln!(out, "\nunion {{")?;
-        for (p, _) in cells
-            .by_ref()
-            .take_while(|(_, dd)| *dd <= (i + 1) * max_d / n)
-        {
-            writeln!(out, "  sphere {{ <{}, {}, {}>, 1 }}", p.x, p.y, p.z)?;
+        for p in dla.cells() {
+            let d = center.dist2(*p);
+            if d <= max_d && (i == 0 || d > min_d) {
+                writeln!(out, "  sphere {{ <{}, {}, {}>, 1 }}", p.x, p.y, p.z)?;
+            }
         }
 
         let (r, g, b) = match 5 + i / gradients {
@@ -217,16 +1364,37 @@ camera {{
         )?;
     }
 
-    println!(
-        r#"## PovRay Scene
+    Ok(())
+}
 
-The DLA scene has been saved as a PovRay scene ({path}) which is possible to
-render with a command like the following
+/// Write the cells of each species of the DLA with its own texture.
+fn write_pov_species(out: &mut impl Write, dla: &Dla) -> io::Result<()> {
+    const PALETTE: [(f64, f64, f64); 6] = [
+        (0.34, 0.7, 0.03),
+        (0.85, 0.84, 0.0),
+        (0.8, 0.2, 0.1),
+        (0.1, 0.4, 0.8),
+        (0.6, 0.2, 0.7),
+        (0.9, 0.5, 0.1),
+    ];
+
+    for species in 0..dla.species().len() {
+        writeln!(out, "\n// species {}\nunion {{", species)?;
+        for p in dla.cells().filter(|c| dla.species_of(**c) == species) {
+            writeln!(out, "  sphere {{ <{}, {}, {}>, 1 }}", p.x, p.y, p.z)?;
+        }
 
-`povray +A +W1600 +H1600 {path}`
-"#,
-        path = path.display()
-    );
+        let (r, g, b) = PALETTE[species % PALETTE.len()];
+        writeln!(
+            out,
+            r#"  texture {{
+    pigment {{ color rgb<{}, {}, {}> }}
+    finish {{ phong 0.5 }}
+  }}
+}}"#,
+            r, g, b
+        )?;
+    }
 
     Ok(())
 }
@@ -238,7 +1406,7 @@ fn save_js_scene(
         camera,
         lights,
     }: &Scene,
-) -> io::Result<()> {
+) -> io::Result<PathBuf> {
     let path = path.with_extension("js");
     let mut out = BufWriter::new(File::create(&path)?);
 
@@ -279,8 +1447,20 @@ var DLA = {{
     particles: ["#
     )?;
 
+    let has_species = dla.species().len() > 1;
     for p in dla.cells() {
-        writeln!(out, "        {{ x: {}, y: {}, z: {} }},", p.x, p.y, p.z)?;
+        if has_species {
+            writeln!(
+                out,
+                "        {{ x: {}, y: {}, z: {}, species: {} }},",
+                p.x,
+                p.y,
+                p.z,
+                dla.species_of(*p)
+            )?;
+        } else {
+            writeln!(out, "        {{ x: {}, y: {}, z: {} }},", p.x, p.y, p.z)?;
+        }
     }
 
     writeln!(
@@ -298,26 +1478,265 @@ single object `DLA` that has the `particles` alongside a `camera` and `lights`.
         path = path.display()
     );
 
-    Ok(())
+    Ok(path)
 }
 
-fn save_csv_scene(path: &Path, Scene { dla, .. }: &Scene) -> io::Result<()> {
-    let path = path.with_extension("csv");
-    let mut out = BufWriter::new(File::create(&path)?);
+fn save_csv_scene(path: &Path, Scene { dla, .. }: &Scene) -> io::Result<PathBuf> {
+    save_cells(path, SceneFormat::Csv, dla)
+}
+
+fn save_ply_scene(path: &Path, Scene { dla, .. }: &Scene) -> io::Result<PathBuf> {
+    save_cells(path, SceneFormat::Ply, dla)
+}
+
+/// Save the cells of the DLA streaming them from its octree.
+fn save_cells(path: &Path, format: SceneFormat, dla: &Dla) -> io::Result<PathBuf> {
+    let mut out = CellWriter::create(path, format, dla.species().len() > 1)?;
+    for c in dla.cells() {
+        out.write(*c, dla.species_of(*c))?;
+    }
+    out.finish()
+}
+
+impl Recorder<'_> {
+    fn record(&mut self, cell: Vec3, species: SpeciesId, parent: Option<Vec3>) -> io::Result<()> {
+        let parent = parent.and_then(|p| self.indices.get(&p).copied());
+        if self.parents {
+            self.indices.insert(cell, self.dump.written());
+        }
+
+        self.dump.write(&dump::Record {
+            cell,
+            species,
+            parent,
+        })?;
+        self.streams
+            .iter_mut()
+            .try_for_each(|s| s.write(cell, species))
+    }
+}
+
+impl Observer for Recorder<'_> {
+    fn notify(&mut self, e: &Event) {
+        if let Event::Attached {
+            cell,
+            parent,
+            species,
+        } = e
+        {
+            if self.error.is_none() {
+                self.error = self.record(*cell, *species, Some(*parent)).err();
+            }
+
+            self.attached += 1;
+
+            if self.attached % 100 == 0 {
+                print!(
+                    "\rgenerated {} particles, progress: {}%",
+                    self.attached,
+                    self.attached * 100 / self.particles
+                );
+                let _ = io::stdout().flush();
+            }
+        }
+    }
+
+    fn interrupted(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+impl CellWriter {
+    /// Whether the cells can be saved in the given format one at a time.
+    fn supports(format: SceneFormat) -> bool {
+        matches!(format, SceneFormat::Csv | SceneFormat::Ply)
+    }
+
+    /// Create the file the cells will be appended to, the format must be
+    /// supported.
+    ///
+    /// The header of a PLY file must start with the number of vertices, which
+    /// is unknown until the end, so they're appended to a `.part` file next to
+    /// it instead that's copied after the header once they're all written.
+    fn create(path: &Path, format: SceneFormat, has_species: bool) -> io::Result<Self> {
+        let path = path.with_extension(format.name());
+        let out = match format {
+            SceneFormat::Ply => OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(part_path(&path))?,
+            _ => File::create(&path)?,
+        };
+
+        Ok(CellWriter {
+            format,
+            path,
+            out: BufWriter::new(out),
+            has_species,
+            len: 0,
+        })
+    }
+
+    fn write(&mut self, c: Vec3, species: SpeciesId) -> io::Result<()> {
+        self.len += 1;
+
+        match self.format {
+            SceneFormat::Ply => writeln!(self.out, "{} {} {} {}", c.x, c.y, c.z, species),
+            _ if self.has_species => writeln!(self.out, "{},{},{},{}", c.x, c.y, c.z, species),
+            _ => writeln!(self.out, "{},{},{}", c.x, c.y, c.z),
+        }
+    }
+
+    /// Flush all the cells, patch the header if needed and return the path of
+    /// the saved file.
+    fn finish(mut self) -> io::Result<PathBuf> {
+        self.out.flush()?;
+
+        if self.format == SceneFormat::Ply {
+            let mut vertices = self.out.into_inner().map_err(|e| e.into_error())?;
+            vertices.seek(SeekFrom::Start(0))?;
+
+            let mut out = BufWriter::new(File::create(&self.path)?);
+            write!(
+                out,
+                r#"ply
+format ascii 1.0
+comment 3D DLA geometry - generated by github.com/danieledapo/dla
+element vertex {}
+property int x
+property int y
+property int z
+property uint species
+end_header
+"#,
+                self.len
+            )?;
+            io::copy(&mut vertices, &mut out)?;
+            out.flush()?;
+
+            drop(vertices);
+            fs::remove_file(part_path(&self.path))?;
+        }
+
+        let path = self.path.display();
+        match self.format {
+            SceneFormat::Ply => println!(
+                r#"## Ply Scene
+
+The cells that form the DLA have been saved as a point cloud in a PLY file
+({path}) alongside their species.
+"#
+            ),
+            _ => println!(
+                r#"## Csv Scene
+
+The positions (x,y,z) of all the cells that form the DLA have been saved as a CSV file ({path}).{species}
+"#,
+                species = if self.has_species {
+                    "\nThe species of each cell is saved as the fourth column."
+                } else {
+                    ""
+                }
+            ),
+        }
+
+        Ok(self.path)
+    }
+}
+
+/// The file where the vertices of a PLY scene are written until they can be
+/// appended to its header, see `CellWriter::create`.
+fn part_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Render the DLA with an orthographic camera looking at its center, each
+/// cell is drawn as a disc whose brightness fades with the depth.
+fn save_ppm_image(path: &Path, dla: &Dla, args: &RenderArgs) -> io::Result<PathBuf> {
+    let (width, height) = (args.width as usize, args.height as usize);
+
+    let bounds = Sphere::bounding(&dla.bbox());
+    let view = Transform::translate(-bounds.center)
+        .then(&Transform::rotate(
+            Axis::Y.unit().into(),
+            args.yaw.to_radians(),
+        ))
+        .then(&Transform::rotate(
+            Axis::X.unit().into(),
+            args.pitch.to_radians(),
+        ));
+
+    // fit the bounding sphere in the image so that the DLA is never cropped
+    // whatever the rotation
+    let scale = width.min(height) as f64 / (2.0 * bounds.radius.max(1.0) * 1.05);
+    let disc_radius = (scale / 2.0).max(0.5);
+
+    let mut depth = vec![f64::INFINITY; width * height];
+    let mut pixels = vec![[0_u8; 3]; width * height];
 
     for c in dla.cells() {
-        writeln!(out, "{},{},{}", c.x, c.y, c.z)?;
+        let p = view.apply(Vec3f::from(*c));
+
+        // the y axis of the image points down
+        let px = width as f64 / 2.0 + p.x * scale;
+        let py = height as f64 / 2.0 - p.y * scale;
+
+        let brightness = 0.3 + 0.7 * (1.0 - (p.z / bounds.radius.max(1.0) + 1.0) / 2.0);
+        let color = species_color(dla.species_of(*c)).map(|c| (c * brightness * 255.0) as u8);
+
+        let r = disc_radius.ceil() as i64;
+        for y in (py as i64 - r)..=(py as i64 + r) {
+            for x in (px as i64 - r)..=(px as i64 + r) {
+                if x < 0 || y < 0 || x >= width as i64 || y >= height as i64 {
+                    continue;
+                }
+
+                let (dx, dy) = (x as f64 + 0.5 - px, y as f64 + 0.5 - py);
+                if dx * dx + dy * dy > disc_radius * disc_radius {
+                    continue;
+                }
+
+                let i = y as usize * width + x as usize;
+                if p.z < depth[i] {
+                    depth[i] = p.z;
+                    pixels[i] = color;
+                }
+            }
+        }
     }
 
+    let mut out = BufWriter::new(File::create(path)?);
+    write!(out, "P6\n{} {}\n255\n", width, height)?;
+    for p in &pixels {
+        out.write_all(p)?;
+    }
+    out.flush()?;
+
     println!(
-        r#"## Csv Scene
+        r#"## PPM Image
 
-The positions (x,y,z) of all the cells that form the DLA have been saved as a CSV file ({path}).
+The DLA has been rendered as a {width}x{height} PPM image ({path}).
 "#,
         path = path.display()
     );
 
-    Ok(())
+    Ok(path.to_path_buf())
+}
+
+/// Base color of the cells of the given species when rendering an image.
+fn species_color(species: SpeciesId) -> [f64; 3] {
+    const PALETTE: [[f64; 3]; 4] = [
+        [0.45, 0.85, 0.35],
+        [0.95, 0.55, 0.2],
+        [0.35, 0.6, 0.95],
+        [0.9, 0.35, 0.65],
+    ];
+
+    PALETTE[species % PALETTE.len()]
 }
 
 impl Scene {
@@ -341,8 +1760,13 @@ impl Scene {
         };
 
         let mut lights = vec![];
+        let center = Vec3f::from(scene_bbox.center());
         let mut add_light = |pt: Vec3, intensity| {
-            let position = pt + (pt - scene_bbox.center()).normalized() * away_dist;
+            // move the light away from the center of the scene
+            let pt = Vec3f::from(pt);
+            let position = Ray::new(pt, (pt - center).normalized())
+                .at(away_dist as f64)
+                .round();
             lights.push(Light {
                 position,
                 intensity,
@@ -417,6 +1841,194 @@ impl Scene {
     }
 }
 
+impl Model {
+    /// The model growing a DLA with the given growth model.
+    fn of(growth_model: GrowthModel) -> Self {
+        match growth_model {
+            GrowthModel::Diffusion => Model::Dla,
+            GrowthModel::Ballistic => Model::Ballistic,
+            GrowthModel::Eden => Model::Eden,
+            GrowthModel::Lattice(_) => Model::Lattice,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Model::Dla => "dla",
+            Model::Ballistic => "ballistic",
+            Model::Eden => "eden",
+            Model::Lattice => "lattice",
+            Model::Dlca => "dlca",
+        }
+    }
+}
+
+impl std::str::FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "dla" => Ok(Model::Dla),
+            "ballistic" => Ok(Model::Ballistic),
+            "eden" => Ok(Model::Eden),
+            "lattice" => Ok(Model::Lattice),
+            "dlca" => Ok(Model::Dlca),
+            s => Err(format!("`{}` is not a valid model", s)),
+        }
+    }
+}
+
+/// Save the bounding boxes of the octree nodes as the edges of an OBJ file.
+fn save_octree_obj(path: &Path, dla: &Dla) -> io::Result<PathBuf> {
+    let path = octree_path(path, "obj");
+    let mut out = BufWriter::new(File::create(&path)?);
+
+    writeln!(
+        out,
+        "# DLA octree - generated by github.com/danieledapo/dla"
+    )?;
+
+    let mut nodes = 0;
+    for (_, bbox) in dla.octree().node_bboxes() {
+        let (l, u) = (bbox.lower(), bbox.upper());
+        for i in 0..8 {
+            let pick = |bit: usize, l: i64, u: i64| if i & bit == 0 { l } else { u };
+            writeln!(
+                out,
+                "v {} {} {}",
+                pick(1, l.x, u.x),
+                pick(2, l.y, u.y),
+                pick(4, l.z, u.z)
+            )?;
+        }
+
+        // vertices are 1-based and the i-th corner has the bits of i set on
+        // the upper side of the box, edges connect corners differing by a bit
+        let base = nodes * 8 + 1;
+        for i in 0..8 {
+            for bit in [1, 2, 4].into_iter().filter(|b| i & b == 0) {
+                writeln!(out, "l {} {}", base + i, base + (i | bit))?;
+            }
+        }
+
+        nodes += 1;
+    }
+
+    println!(
+        r#"## Octree wireframe
+
+The bounding boxes of the {nodes} nodes of the octree have been saved as a
+wireframe OBJ file ({path}).
+"#,
+        nodes = nodes,
+        path = path.display()
+    );
+
+    Ok(path)
+}
+
+/// Save the bounding boxes of the octree nodes as a POV-Ray scene, deeper
+/// nodes are drawn with thinner and warmer wires.
+fn save_octree_pov(path: &Path, dla: &Dla) -> io::Result<PathBuf> {
+    let path = octree_path(path, "pov");
+    let mut out = BufWriter::new(File::create(&path)?);
+
+    let octree = dla.octree();
+    let max_depth = octree.node_bboxes().map(|(d, _)| d).max().unwrap_or(0);
+    let bbox = dla.bbox();
+    let d = bbox.dimensions();
+    let away_dist = d.x.max(d.y).max(d.z).max(1) * 2;
+
+    #[rustfmt::skip]
+    writeln!(
+        out,
+        r#"// DLA octree - generated by github.com/danieledapo/dla
+
+#version 3.7;
+
+#include "colors.inc"
+#include "shapes.inc"
+
+global_settings {{ assumed_gamma 1.0 }}
+
+background {{ color Black }}
+
+camera {{
+  location <{}, {}, {}>
+  look_at <{}, {}, {}>
+}}
+
+light_source {{ <{}, {}, {}> color White }}
+
+#macro Node(Lower, Upper, Depth)
+  object {{
+    Wire_Box(Lower, Upper, 0.5 / (Depth + 1), 0)
+    pigment {{ color rgb <Depth / {}, 0.4, 1 - Depth / {}> }}
+    finish {{ ambient 0.6 }}
+  }}
+#end
+"#,
+        bbox.center().x - away_dist, bbox.center().y + away_dist, bbox.center().z - away_dist,
+        bbox.center().x, bbox.center().y, bbox.center().z,
+        bbox.center().x - away_dist, bbox.center().y + away_dist * 2, bbox.center().z - away_dist,
+        max_depth.max(1), max_depth.max(1),
+    )?;
+
+    let mut nodes = 0;
+    for (depth, bbox) in octree.node_bboxes() {
+        let (l, u) = (bbox.lower(), bbox.upper());
+        writeln!(
+            out,
+            "Node(<{}, {}, {}>, <{}, {}, {}>, {})",
+            l.x, l.y, l.z, u.x, u.y, u.z, depth
+        )?;
+        nodes += 1;
+    }
+
+    println!(
+        r#"## Octree wireframe
+
+The bounding boxes of the {nodes} nodes of the octree have been saved as a
+povray scene ({path}).
+"#,
+        nodes = nodes,
+        path = path.display()
+    );
+
+    Ok(path)
+}
+
+/// Path of an octree debug export, placed next to the scene files.
+fn octree_path(path: &Path, extension: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push("-octree");
+
+    path.with_file_name(name).with_extension(extension)
+}
+
+impl std::str::FromStr for WireframeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "obj" => Ok(WireframeFormat::Obj),
+            "povray" => Ok(WireframeFormat::Povray),
+            s => Err(format!("`{}` is not a valid wireframe format", s)),
+        }
+    }
+}
+
+impl SceneFormat {
+    fn name(self) -> &'static str {
+        match self {
+            SceneFormat::Povray => "povray",
+            SceneFormat::Js => "js",
+            SceneFormat::Csv => "csv",
+            SceneFormat::Ply => "ply",
+        }
+    }
+}
+
 impl std::str::FromStr for SceneFormat {
     type Err = String;
 
@@ -425,7 +2037,354 @@ impl std::str::FromStr for SceneFormat {
             "povray" => Ok(SceneFormat::Povray),
             "javascript" | "js" => Ok(SceneFormat::Js),
             "csv" => Ok(SceneFormat::Csv),
+            "ply" => Ok(SceneFormat::Ply),
             s => Err(format!("`{}` is not a valid scene format", s)),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fs;
+
+    /// Parse the arguments of the simulate subcommand as the CLI would.
+    fn simulate_args(args: &[&str]) -> SimulateArgs {
+        let args = ["dla", "simulate"].iter().chain(args);
+        match App::try_parse_from(args).unwrap().command {
+            Command::Simulate(args) => *args,
+            c => panic!("unexpected command {:?}", c),
+        }
+    }
+
+    /// A path in the temporary directory unique to this test process.
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("dla-test-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn points_are_parsed() {
+        assert_eq!(parse_vec3("1,-2,3"), Ok(Vec3::new(1, -2, 3)));
+        assert_eq!(parse_vec3(" 1, 2 ,3 "), Ok(Vec3::new(1, 2, 3)));
+
+        assert!(parse_vec3("").is_err());
+        assert!(parse_vec3("1,2").is_err());
+        assert!(parse_vec3("1,2,3,4").is_err());
+        assert!(parse_vec3("1,x,3").is_err());
+        assert!(parse_vec3("1.5,2,3").is_err());
+    }
+
+    #[test]
+    fn shapes_are_parsed() {
+        assert!(matches!(
+            parse_shape("sphere:1,2,3,4"),
+            Ok(Shape::Sphere { radius: 4, .. })
+        ));
+        assert!(matches!(
+            parse_shape("cylinder:y,0,0,0,2,5"),
+            Ok(Shape::Cylinder {
+                axis: Axis::Y,
+                height: 5,
+                ..
+            })
+        ));
+
+        for (shape, error) in [
+            ("sphere:0,0,0,-1", "radius of a shape cannot be negative"),
+            (
+                "cylinder:z,0,0,0,-2,5",
+                "radius of a shape cannot be negative",
+            ),
+            (
+                "cylinder:z,0,0,0,2,-5",
+                "height of a shape cannot be negative",
+            ),
+            ("sphere:0,0,0", "wrong number of parameters"),
+            ("cone:0,0,0,1", "not a valid shape"),
+        ] {
+            let err = parse_shape(shape).unwrap_err();
+            assert!(err.contains(error), "`{}` doesn't mention `{}`", err, error);
+        }
+    }
+
+    #[test]
+    fn csv_scenes_are_loaded() {
+        let path = temp_path("scene.csv");
+
+        fs::write(&path, "0,0,0\n\n1, 0, 0,2\n 2,0,0 \n").unwrap();
+        let dla = load_csv_scene(&path).unwrap();
+        assert_eq!(dla.len(), 3);
+        assert!(dla.contains(Vec3::new(2, 0, 0)));
+        assert_eq!(dla.species_of(Vec3::new(1, 0, 0)), 2);
+        assert_eq!(dla.species().len(), 3);
+
+        for (contents, error) in [
+            ("", "the DLA needs at least one seed"),
+            ("0,0,0\n1,2\n", ":2: expected"),
+            ("0,0,0,-1\n", ":1: expected"),
+            ("0,0,zero\n", ":1: `zero` is not a valid coordinate"),
+            ("0,0,0,1000000\n", "species"),
+            ("0,0,9223372036854775807\n", "0,0,9223372036854775807"),
+        ] {
+            fs::write(&path, contents).unwrap();
+            let err = load_csv_scene(&path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert!(
+                err.to_string().contains(error),
+                "`{}` doesn't mention `{}`",
+                err,
+                error
+            );
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn species_are_counted_on_merged_aggregates() {
+        let mut dla = Dla::from_cells(10, 8, vec![(Vec3::new(0, 0, 0), 0)]).unwrap();
+        assert_eq!(species_counts(&dla), vec![1]);
+
+        let other = Dla::from_cells(
+            10,
+            8,
+            vec![
+                (Vec3::new(1, 0, 0), 0),
+                (Vec3::new(2, 0, 0), 3),
+                (Vec3::new(3, 0, 0), 3),
+            ],
+        )
+        .unwrap();
+        dla.merge(&other);
+        assert_eq!(species_counts(&dla), vec![2, 0, 0, 2]);
+    }
+
+    #[test]
+    fn ppm_images_have_the_requested_size() {
+        let path = temp_path("image.ppm");
+        let args = match App::try_parse_from([
+            "dla", "render", "in.dla", "--width", "40", "--height", "20",
+        ])
+        .unwrap()
+        .command
+        {
+            Command::Render(args) => args,
+            c => panic!("unexpected command {:?}", c),
+        };
+        let line = (0..5).map(|x| (Vec3::new(x, 0, 0), x as usize % 2));
+        let dla = Dla::from_cells(10, 8, line).unwrap();
+
+        assert_eq!(save_ppm_image(&path, &dla, &args).unwrap(), path);
+
+        let image = fs::read(&path).unwrap();
+        let header = b"P6\n40 20\n255\n";
+        assert_eq!(&image[..header.len()], header);
+
+        let pixels = image[header.len()..].chunks(3).collect::<Vec<_>>();
+        assert_eq!(pixels.len(), 40 * 20);
+        // the cells are drawn around the center while the corners are empty
+        assert_ne!(pixels[10 * 40 + 20], [0, 0, 0]);
+        assert_eq!(pixels[0], [0, 0, 0]);
+        assert_eq!(pixels[40 * 20 - 1], [0, 0, 0]);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn streamed_scenes_match_the_saved_ones() {
+        let sorted = |path: PathBuf| {
+            let contents = fs::read_to_string(&path).unwrap();
+            fs::remove_file(&path).unwrap();
+
+            // the cells are streamed in the order they're attached, but saved
+            // in the order of the octree
+            let (header, cells) = match contents.find("end_header\n") {
+                Some(end) => contents.split_at(end + "end_header\n".len()),
+                None => ("", contents.as_str()),
+            };
+            let mut cells = cells.lines().map(String::from).collect::<Vec<_>>();
+            cells.sort();
+            (header.to_string(), cells)
+        };
+
+        let mut scenes = vec![];
+        for (name, stream) in [("saved", &[][..]), ("streamed", &["--stream"])] {
+            let output = temp_path(name);
+            let args = simulate_args(
+                &[
+                    &["-p", "50", "--seed", "42", "-s", "csv", "-s", "ply"][..],
+                    &["--species", "8:1:1,1", "--species", "8:1:1,1"],
+                    stream,
+                    &[output.to_str().unwrap()],
+                ]
+                .concat(),
+            );
+            simulate(&args).unwrap();
+
+            fs::remove_file(dump_path(&output)).unwrap();
+            assert!(!part_path(&output.with_extension("ply")).exists());
+            scenes.push((
+                sorted(output.with_extension("csv")),
+                sorted(output.with_extension("ply")),
+            ));
+        }
+
+        let ((csv, ply), (streamed_csv, streamed_ply)) = (&scenes[0], &scenes[1]);
+        assert_eq!(csv, streamed_csv);
+        assert_eq!(ply, streamed_ply);
+        assert_eq!(csv.1.len(), 51);
+        assert!(csv.1.iter().any(|l| l.ends_with(",1")));
+        assert!(ply.0.contains("element vertex 51\n"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn simulation_stops_on_the_first_write_error() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+
+        // the seed fits in the buffer, the first flush fails a few particles
+        // later
+        let full = OpenOptions::new().write(true).open("/dev/full").unwrap();
+        let mut streams = [CellWriter {
+            format: SceneFormat::Csv,
+            path: PathBuf::from("/dev/full"),
+            out: BufWriter::with_capacity(64, full),
+            has_species: false,
+            len: 0,
+        }];
+
+        let path = temp_path("full.dla");
+        let dump = dump::Writer::new(
+            BufWriter::new(File::create(&path).unwrap()),
+            dump::Header::of(&dla, None),
+        )
+        .unwrap();
+        let mut recorder = Recorder {
+            dump,
+            streams: &mut streams,
+            parents: false,
+            indices: HashMap::new(),
+            particles: 100_000,
+            attached: 0,
+            error: None,
+        };
+        recorder.record(Vec3::new(0, 0, 0), 0, None).unwrap();
+
+        let conditions = [StopCondition::Particles(100_000)];
+        let stop = dla.run_until_with(&mut rng, &conditions, &mut recorder);
+        assert_eq!(stop, Ok(None));
+        assert!(recorder.error.is_some());
+        assert_eq!(dla.len(), recorder.attached + 1);
+        assert!(dla.len() < 100);
+
+        drop(recorder);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn dlca_rejects_the_options_it_ignores() {
+        let mut rng = StdRng::seed_from_u64(42);
+
+        for extra in [
+            &["--sphere", "10"][..],
+            &["--plane", "y=10"],
+            &["--species", "8:1:1"],
+            &["--obstacle", "sphere:0,0,0,2"],
+            &["--parents"],
+            &["--spawn-radius", "20"],
+            &["--connectivity", "18"],
+            &["--stickiness", "0.5"],
+            &["--anisotropy-strength", "0.5"],
+            &["--hits", "3"],
+            &["--attractor-pull", "0.5"],
+            &["--attractor-stickiness", "0.5"],
+            &["--attractor-range", "5"],
+        ] {
+            let args = simulate_args(&[&["-m", "dlca", "-p", "10"], extra].concat());
+            let err = simulate_dlca(&args, &mut rng).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(err.to_string().starts_with(extra[0]));
+        }
+
+        let args = simulate_args(&["-m", "dlca", "-p", "10", "--box-size", "10"]);
+        let (dla, _) = simulate_dlca(&args, &mut rng).unwrap();
+        assert_eq!(dla.len(), 10);
+    }
+
+    #[test]
+    fn merge_rejects_extra_offsets() {
+        let args = [
+            "dla", "merge", "a.dla", "--offset", "1,0,0", "--offset", "2,0,0",
+        ];
+        let args = match App::try_parse_from(args).unwrap().command {
+            Command::Merge(args) => args,
+            c => panic!("unexpected command {:?}", c),
+        };
+
+        let err = merge(&args).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "2 offsets were given for only 1 inputs");
+    }
+
+    #[test]
+    fn periodic_box_must_not_be_empty() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let args = simulate_args(&["--periodic", "--box-size", "0", "-p", "10"]);
+
+        let err = simulate_dla(&args, GrowthModel::Diffusion, 42, &mut [], &mut rng).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(err.to_string(), "invalid periodic box size 0");
+    }
+
+    #[test]
+    fn only_dla_walkers_are_pulled_by_attractors() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let attractor = ["--attractor", "sphere:20,0,0,2", "-p", "10"];
+
+        let args = simulate_args(&[&["-m", "ballistic"][..], &attractor].concat());
+        let (dla, _) = simulate_dla(&args, GrowthModel::Ballistic, 42, &mut [], &mut rng).unwrap();
+        assert_eq!(dla.len(), 11);
+
+        let pulled = [
+            &["-m", "lattice", "--attractor-pull", "0.3"][..],
+            &attractor,
+        ]
+        .concat();
+        let args = simulate_args(&pulled);
+        let model = GrowthModel::Lattice(args.connectivity());
+        let err = simulate_dla(&args, model, 42, &mut [], &mut rng).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn report_is_valid_json() {
+        let args = simulate_args(&["-p", "20", "-s", "ply", "out \"1\""]);
+        let dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0), Vec3::new(3, -2, 1)]).unwrap();
+        let outputs = [PathBuf::from("out \"1\".dla"), PathBuf::from("tab\t.ply")];
+
+        let report = Report::new(
+            &args,
+            42,
+            "reached 20 particles",
+            time::Duration::from_millis(1500),
+            &dla,
+            &outputs,
+        );
+        let json = serde_json::to_string(&report).unwrap();
+        let json = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+
+        assert_eq!(json["parameters"]["model"], "dla");
+        assert_eq!(json["parameters"]["particles"], 20);
+        assert_eq!(json["parameters"]["scene_formats"][0], "ply");
+        assert_eq!(json["parameters"]["output"], "out \"1\"");
+        assert_eq!(json["seed"], 42);
+        assert_eq!(json["cells"], 2);
+        assert_eq!(json["bbox"]["lower"]["y"], -2);
+        assert_eq!(json["bbox"]["upper"]["x"], 3);
+        assert_eq!(json["volume"], dla.bbox().volume());
+        assert_eq!(json["duration_secs"], 1.5);
+        assert_eq!(json["outputs"][1], "tab\t.ply");
+    }
+}
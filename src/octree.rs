@@ -1,54 +1,177 @@
-use hashbrown::hash_set;
-use hashbrown::HashSet;
+use std::mem::size_of;
 
+use crate::domain::Domain;
 use crate::geo::{Bbox, Vec3};
 
-const MAX_LEAF_SIZE: usize = 64;
-
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// Default maximum number of points a leaf can hold before being split.
+pub const MAX_LEAF_SIZE: usize = 64;
+
+/// Nodes with at least this many points are loaded in parallel by the bulk
+/// loader.
+const PARALLEL_BULK_LOAD_SIZE: usize = 1 << 16;
+
+/// Number of bits of each coordinate that fit in a Morton code.
+const MORTON_BITS: u32 = 42;
+
+/// An octree of integer points.
+///
+/// Every node owns the points `p` such that `lower < p <= upper` along all the
+/// axes of its bounding box, this makes the children of a cube whose side is a
+/// power of two line up with the bits of the coordinates and allows to grow
+/// the root by just adding parent levels.
+///
+/// Nodes and points live in a single arena: the eight children of a branch
+/// are stored next to each other and every leaf owns a contiguous range of
+/// points whose capacity is a power of two.
+#[derive(Clone, Debug)]
 pub struct Octree {
-    root: Option<Node>,
-    outside: HashSet<Vec3>,
+    arena: Arena,
+    root: Option<u32>,
     len: usize,
     rebuilt_count: usize,
+    max_leaf_size: usize,
+}
+
+/// How much memory an octree is using.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MemoryUsage {
+    /// Number of nodes, both branches and leaves.
+    pub nodes: usize,
+
+    /// Number of leaves.
+    pub leaves: usize,
+
+    /// Number of slots reserved for points, including the ones not in use.
+    pub point_slots: usize,
+
+    /// Total number of bytes allocated by the octree.
+    pub bytes: usize,
+}
+
+/// Statistics about the shape of an octree, useful to understand why queries
+/// are slow.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OctreeStats {
+    /// Number of branches.
+    pub branches: usize,
+
+    /// Number of leaves, including the empty ones.
+    pub leaves: usize,
+
+    /// Number of leaves without any point.
+    pub empty_leaves: usize,
+
+    /// Number of leaves at each depth, the root is at depth 0.
+    pub depth_histogram: Vec<usize>,
+
+    /// Number of leaves holding a given number of points, indexed by the
+    /// number of points.
+    pub leaf_occupancy: Vec<usize>,
+
+    /// How many times the root had to grow to fit new points.
+    pub rebuilt_count: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Arena {
+    nodes: Vec<Node>,
+    points: Vec<Vec3>,
+
+    // ranges of `points` that can be reused, indexed by the log2 of their
+    // capacity
+    free: Vec<Vec<u32>>,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 enum Node {
     Branch {
-        children: Box<[Node; 8]>,
+        // index of the first of the eight children
+        children: u32,
         bbox: Bbox,
     },
     Leaf {
-        points: HashSet<Vec3>,
+        start: u32,
+        len: u32,
+        cap: u32,
         bbox: Bbox,
     },
 }
 
-#[derive(Debug)]
-struct OctreeIter<'o> {
-    len: usize,
-    stack: Vec<&'o Node>,
-    current: Option<hash_set::Iter<'o, Vec3>>,
-}
-
 impl Octree {
     pub fn new() -> Self {
+        Octree::with_leaf_size(MAX_LEAF_SIZE)
+    }
+
+    /// Create an empty octree whose leaves hold at most `max_leaf_size`
+    /// points. Smaller leaves make queries faster at the cost of more nodes.
+    pub fn with_leaf_size(max_leaf_size: usize) -> Self {
         Octree {
+            arena: Arena::default(),
             root: None,
-            outside: HashSet::with_capacity(MAX_LEAF_SIZE),
             len: 0,
             rebuilt_count: 0,
+            max_leaf_size: max_leaf_size.max(1),
         }
     }
 
     pub fn with_hint(bbox: Bbox) -> Self {
-        Octree {
-            root: Some(Node::new(bbox, HashSet::new())),
-            outside: HashSet::with_capacity(MAX_LEAF_SIZE),
-            len: 0,
-            rebuilt_count: 0,
-        }
+        let bbox = bbox.expand(bbox.lower() - 1);
+
+        let mut octree = Octree::new();
+        let root = octree.arena.leaf(bbox, &[]);
+        octree.arena.nodes.push(root);
+        octree.root = Some(0);
+        octree
+    }
+
+    /// Build an octree from the given points by sorting them along a Morton
+    /// curve and building the nodes of different branches in parallel. This is
+    /// the same as collecting the points into an `Octree`.
+    pub fn bulk_load(pts: impl IntoIterator<Item = Vec3>) -> Self {
+        Octree::new().bulk_loaded(pts)
+    }
+
+    fn bulk_loaded(mut self, pts: impl IntoIterator<Item = Vec3>) -> Self {
+        let mut pts = pts.into_iter().collect::<Vec<_>>();
+
+        let bbox = match pts.split_first() {
+            None => return self,
+            Some((first, rest)) => rest.iter().fold(Bbox::new(*first), |b, p| b.expand(*p)),
+        };
+
+        // the root is a cube whose side is a power of two so that the Morton
+        // code of a point relative to it tells in which child it ends up
+        let d = bbox.dimensions();
+        let bits = 64 - (d.x.max(d.y).max(d.z) as u64).leading_zeros();
+        let lower = bbox.lower() - 1;
+
+        self.arena = Arena::default();
+
+        let root = if bits > MORTON_BITS {
+            pts.sort_unstable_by_key(|p| (p.x, p.y, p.z));
+            pts.dedup();
+            self.len = pts.len();
+
+            self.arena
+                .build(bbox.expand(lower), pts, self.max_leaf_size)
+        } else {
+            let side = 1_i64 << bits;
+            let root_bbox = Bbox::new(lower).expand(lower + side);
+
+            let mut coded = morton_codes(&pts, bbox.lower(), bits);
+            drop(pts);
+
+            coded.sort_unstable_by_key(|(code, _)| *code);
+            coded.dedup_by_key(|(code, _)| *code);
+            self.len = coded.len();
+
+            self.arena
+                .par_bulk_build(root_bbox, bits, &coded, self.max_leaf_size)
+        };
+
+        self.arena.nodes.push(root);
+        self.root = Some(self.arena.nodes.len() as u32 - 1);
+        self
     }
 
     pub fn len(&self) -> usize {
@@ -59,246 +182,576 @@ impl Octree {
         self.len() == 0
     }
 
+    pub fn max_leaf_size(&self) -> usize {
+        self.max_leaf_size
+    }
+
+    /// How many times the root of the octree had to grow to fit new points.
     pub fn rebuilt_count(&self) -> usize {
         self.rebuilt_count
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = &Vec3> {
-        let stack = self.root.as_ref().map_or_else(Vec::new, |c| vec![c]);
+    /// Report how much memory the octree is using.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let arena = &self.arena;
 
-        OctreeIter {
-            stack,
-            current: None,
-            len: self.len,
+        MemoryUsage {
+            nodes: arena.nodes.len(),
+            leaves: arena
+                .nodes
+                .iter()
+                .filter(|n| matches!(n, Node::Leaf { .. }))
+                .count(),
+            point_slots: arena.points.len(),
+            bytes: arena.nodes.capacity() * size_of::<Node>()
+                + arena.points.capacity() * size_of::<Vec3>()
+                + arena
+                    .free
+                    .iter()
+                    .map(|f| f.capacity() * size_of::<u32>() + size_of::<Vec<u32>>())
+                    .sum::<usize>(),
         }
-        .chain(self.outside.iter())
     }
 
-    pub fn add(&mut self, p: Vec3) {
-        if !self.root.as_ref().map_or(false, |n| n.bbox().contains(p)) {
-            if self.outside.insert(p) {
-                self.len += 1;
+    /// Collect statistics about the shape of the octree.
+    pub fn stats(&self) -> OctreeStats {
+        let mut stats = OctreeStats {
+            rebuilt_count: self.rebuilt_count,
+            ..OctreeStats::default()
+        };
+
+        for (depth, node) in self.nodes_with_depth() {
+            let len = match node {
+                Node::Branch { .. } => {
+                    stats.branches += 1;
+                    continue;
+                }
+                Node::Leaf { len, .. } => *len as usize,
+            };
+
+            stats.leaves += 1;
+            if len == 0 {
+                stats.empty_leaves += 1;
             }
 
-            if self.outside.len() > MAX_LEAF_SIZE {
-                // eprintln!("warning: too many points outside bbox, rebuilding tree");
+            if stats.depth_histogram.len() <= depth {
+                stats.depth_histogram.resize(depth + 1, 0);
+            }
+            stats.depth_histogram[depth] += 1;
 
-                let outside_bbox = self
-                    .outside
-                    .iter()
-                    .fold(Bbox::new(*self.outside.iter().next().unwrap()), |b, p| {
-                        b.expand(*p)
-                    });
-
-                let b = match &self.root {
-                    None => outside_bbox,
-                    Some(n) => n.bbox().union(&outside_bbox),
-                };
-
-                // scale bounding box to avoid rebuilding the tree in the near future
-                let b = b.scale(2);
-
-                self.root = Some(Node::new(
-                    b,
-                    self.iter().chain(self.outside.iter()).cloned().collect(),
-                ));
-                self.outside.clear();
-                self.rebuilt_count += 1;
+            if stats.leaf_occupancy.len() <= len {
+                stats.leaf_occupancy.resize(len + 1, 0);
             }
+            stats.leaf_occupancy[len] += 1;
+        }
 
-            return;
+        stats
+    }
+
+    /// The bounding box of every node along with its depth, the root is at
+    /// depth 0.
+    pub fn node_bboxes(&self) -> impl Iterator<Item = (usize, &Bbox)> {
+        self.nodes_with_depth().map(|(depth, n)| (depth, n.bbox()))
+    }
+
+    fn nodes_with_depth(&self) -> impl Iterator<Item = (usize, &Node)> {
+        let mut stack = self.root.map_or_else(Vec::new, |r| vec![(0, r as usize)]);
+
+        std::iter::from_fn(move || {
+            let (depth, i) = stack.pop()?;
+            let node = &self.arena.nodes[i];
+
+            if let Node::Branch { children, .. } = node {
+                let children = *children as usize;
+                stack.extend((children..children + 8).map(|c| (depth + 1, c)));
+            }
+
+            Some((depth, node))
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Vec3> {
+        self.arena.nodes.iter().flat_map(|n| match n {
+            Node::Leaf { start, len, .. } => {
+                &self.arena.points[*start as usize..(*start + *len) as usize]
+            }
+            Node::Branch { .. } => &[],
+        })
+    }
+
+    pub fn add(&mut self, p: Vec3) {
+        self.grow(p);
+
+        let mut i = self.root.unwrap() as usize;
+        while let Node::Branch { children, bbox } = &self.arena.nodes[i] {
+            i = *children as usize + partition_pt(p, bbox.center());
         }
 
-        if self.root.as_mut().unwrap().add(p) {
+        if self.arena.leaf_insert(i, p, self.max_leaf_size) {
             self.len += 1;
         }
     }
 
     pub fn nearest(&self, p: Vec3) -> Option<(Vec3, i64)> {
-        let closest = self.root.as_ref().and_then(|n| n.nearest(p));
-
-        let closest_outside = self
-            .outside
-            .iter()
-            .map(|pt| (*pt, pt.dist2(p)))
-            .min_by_key(|(_, d)| *d);
-
-        match (closest, closest_outside) {
-            (None, None) => None,
-            (Some(n), None) | (None, Some(n)) => Some(n),
-            (Some(n1), Some(n2)) => {
-                if n1.1 < n2.1 {
-                    Some(n1)
+        let mut nearest = None;
+        if let Some(root) = self.root {
+            self.arena.nearest(root as usize, p, &mut nearest);
+        }
+        nearest
+    }
+
+    /// All the points whose squared distance from `p` is at most `radius2`.
+    pub fn within(&self, p: Vec3, radius2: i64) -> Vec<Vec3> {
+        let mut out = vec![];
+        if let Some(root) = self.root {
+            self.arena.within(root as usize, p, radius2, &mut out);
+        }
+        out
+    }
+
+    /// Grow the root by adding parent levels until it contains the given
+    /// point. The current root becomes one of the children of the new root,
+    /// hence no point is moved.
+    fn grow(&mut self, p: Vec3) {
+        let root = match self.root {
+            Some(root) => root as usize,
+            None => {
+                let leaf = self.arena.leaf(Bbox::new(p - 1).expand(p), &[]);
+                self.arena.nodes.push(leaf);
+                self.root = Some(self.arena.nodes.len() as u32 - 1);
+                return;
+            }
+        };
+
+        loop {
+            let (l, u) = {
+                let bbox = self.arena.nodes[root].bbox();
+                if owns(bbox, p) {
+                    break;
+                }
+                (bbox.lower(), bbox.upper())
+            };
+            let side = u - l;
+
+            // grow towards the point along each axis
+            let grow = |p: i64, l: i64, u: i64, side: i64| {
+                if p <= l {
+                    (l - side, u)
                 } else {
-                    Some(n2)
+                    (l, u + side)
                 }
+            };
+            let (lx, ux) = grow(p.x, l.x, u.x, side.x);
+            let (ly, uy) = grow(p.y, l.y, u.y, side.y);
+            let (lz, uz) = grow(p.z, l.z, u.z, side.z);
+
+            let bbox = Bbox::new(Vec3::new(lx, ly, lz)).expand(Vec3::new(ux, uy, uz));
+            let c = bbox.center();
+            let old = partition_pt(u, c);
+
+            let sub_bboxes = split_bbox(&bbox, c);
+            debug_assert_eq!(&sub_bboxes[old], self.arena.nodes[root].bbox());
+
+            // the old root is moved among the new children so that the root
+            // keeps its index
+            let children = self.arena.nodes.len();
+            for b in sub_bboxes {
+                let leaf = self.arena.leaf(b, &[]);
+                self.arena.nodes.push(leaf);
             }
+            self.arena.nodes.swap(root, children + old);
+            self.arena.nodes[root] = Node::Branch {
+                children: children as u32,
+                bbox,
+            };
+
+            self.rebuilt_count += 1;
         }
     }
 }
 
-impl Node {
-    pub fn new(bbox: Bbox, data: HashSet<Vec3>) -> Self {
-        if data.len() <= MAX_LEAF_SIZE {
-            return Node::Leaf { points: data, bbox };
+impl Octree {
+    /// Same as `nearest`, but distances are measured in the given domain. In
+    /// a periodic domain the closest image of every point is considered,
+    /// assuming all the points are inside the domain.
+    pub fn nearest_in(&self, p: Vec3, domain: &Domain) -> Option<(Vec3, i64)> {
+        let bbox = match domain {
+            Domain::Unbounded => return self.nearest(p),
+            Domain::Periodic(bbox) => bbox,
+        };
+
+        let p = domain.wrap(p);
+        let size = bbox.dimensions() + 1;
+
+        let mut nearest: Option<(Vec3, i64)> = None;
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                for dz in -1..=1 {
+                    let image = p + Vec3::new(dx * size.x, dy * size.y, dz * size.z);
+
+                    // images too far from the domain cannot beat the current
+                    // nearest point
+                    if nearest.is_some_and(|(_, d2)| bbox.dist2(image) >= d2) {
+                        continue;
+                    }
+
+                    if let Some((n, d2)) = self.nearest(image) {
+                        if nearest.is_none_or(|(_, min)| d2 < min) {
+                            nearest = Some((n, d2));
+                        }
+                    }
+                }
+            }
+        }
+
+        nearest
+    }
+}
+
+impl Arena {
+    /// A leaf holding the given points.
+    fn leaf(&mut self, bbox: Bbox, pts: &[Vec3]) -> Node {
+        let cap = if pts.is_empty() {
+            0
+        } else {
+            pts.len().next_power_of_two() as u32
+        };
+        let start = self.alloc_points(cap);
+        self.points[start as usize..start as usize + pts.len()].copy_from_slice(pts);
+
+        Node::Leaf {
+            start,
+            len: pts.len() as u32,
+            cap,
+            bbox,
+        }
+    }
+
+    /// Reserve a range of `cap` points, `cap` must be a power of two.
+    fn alloc_points(&mut self, cap: u32) -> u32 {
+        if cap == 0 {
+            return 0;
+        }
+
+        let class = cap.trailing_zeros() as usize;
+        if let Some(start) = self.free.get_mut(class).and_then(|f| f.pop()) {
+            return start;
+        }
+
+        let start = self.points.len();
+        self.points.resize(start + cap as usize, Vec3::new(0, 0, 0));
+        start as u32
+    }
+
+    fn free_points(&mut self, start: u32, cap: u32) {
+        if cap == 0 {
+            return;
+        }
+
+        let class = cap.trailing_zeros() as usize;
+        if self.free.len() <= class {
+            self.free.resize_with(class + 1, Vec::new);
+        }
+        self.free[class].push(start);
+    }
+
+    /// Insert the point in the i-th node, which must be a leaf, splitting it
+    /// if it grows too big. Return whether the point was not already there.
+    fn leaf_insert(&mut self, i: usize, p: Vec3, max_leaf_size: usize) -> bool {
+        let (start, len, cap, bbox) = match &self.nodes[i] {
+            Node::Leaf {
+                start,
+                len,
+                cap,
+                bbox,
+            } => (*start as usize, *len as usize, *cap, bbox.clone()),
+            Node::Branch { .. } => unreachable!(),
+        };
+
+        if self.points[start..start + len].contains(&p) {
+            return false;
+        }
+
+        if len >= max_leaf_size {
+            let mut pts = self.points[start..start + len].to_vec();
+            pts.push(p);
+            self.free_points(start as u32, cap);
+
+            self.nodes[i] = self.build(bbox, pts, max_leaf_size);
+            return true;
+        }
+
+        let (start, cap) = if len as u32 == cap {
+            let new_cap = (cap * 2).max(1);
+            let new_start = self.alloc_points(new_cap) as usize;
+            self.points.copy_within(start..start + len, new_start);
+            self.free_points(start as u32, cap);
+            (new_start, new_cap)
+        } else {
+            (start, cap)
+        };
+
+        self.points[start + len] = p;
+        self.nodes[i] = Node::Leaf {
+            start: start as u32,
+            len: len as u32 + 1,
+            cap,
+            bbox,
+        };
+
+        true
+    }
+
+    /// Build the node owning the given points inside the bbox.
+    fn build(&mut self, bbox: Bbox, data: Vec<Vec3>, max_leaf_size: usize) -> Node {
+        if data.len() <= max_leaf_size {
+            return self.leaf(bbox, &data);
         }
 
         let c = bbox.center();
         let sub_bboxes = split_bbox(&bbox, c);
-        let mut sub_data = [
-            HashSet::with_capacity(MAX_LEAF_SIZE),
-            HashSet::with_capacity(MAX_LEAF_SIZE),
-            HashSet::with_capacity(MAX_LEAF_SIZE),
-            HashSet::with_capacity(MAX_LEAF_SIZE),
-            HashSet::with_capacity(MAX_LEAF_SIZE),
-            HashSet::with_capacity(MAX_LEAF_SIZE),
-            HashSet::with_capacity(MAX_LEAF_SIZE),
-            HashSet::with_capacity(MAX_LEAF_SIZE),
-        ];
+        let mut sub_data = [(); 8].map(|_| Vec::with_capacity(max_leaf_size));
 
         for p in data {
-            let bbox_id = partition_pt(p, c);
-            sub_data[bbox_id].insert(p);
+            sub_data[partition_pt(p, c)].push(p);
         }
 
-        let child = |i: usize, sub_data: &mut [HashSet<Vec3>; 8]| {
-            // swap to avoid to clone
-            let mut d = HashSet::new();
-            std::mem::swap(&mut d, &mut sub_data[i]);
+        let children = self.alloc_children();
+        for (i, (b, d)) in sub_bboxes.into_iter().zip(sub_data).enumerate() {
+            self.nodes[children + i] = self.build(b, d, max_leaf_size);
+        }
 
-            Node::new(sub_bboxes[i].clone(), d)
-        };
+        Node::Branch {
+            children: children as u32,
+            bbox,
+        }
+    }
+
+    /// Build the node covering the given cube of side `2^bits` from points
+    /// sorted by their Morton code relative to the cube.
+    fn bulk_build(
+        &mut self,
+        bbox: Bbox,
+        bits: u32,
+        pts: &[(u128, Vec3)],
+        max_leaf_size: usize,
+    ) -> Node {
+        // a cube of side 1 holds a single point
+        if pts.len() <= max_leaf_size || bits == 0 {
+            let pts = pts.iter().map(|(_, p)| *p).collect::<Vec<_>>();
+            return self.leaf(bbox, &pts);
+        }
+
+        let ranges = octant_ranges(pts, bits);
+        let sub_bboxes = split_bbox(&bbox, bbox.center());
+        let children = self.alloc_children();
 
-        let children = Box::new([
-            child(0, &mut sub_data),
-            child(1, &mut sub_data),
-            child(2, &mut sub_data),
-            child(3, &mut sub_data),
-            child(4, &mut sub_data),
-            child(5, &mut sub_data),
-            child(6, &mut sub_data),
-            child(7, &mut sub_data),
-        ]);
+        for (i, (range, b)) in ranges.iter().zip(sub_bboxes).enumerate() {
+            self.nodes[children + i] = self.bulk_build(b, bits - 1, range, max_leaf_size);
+        }
 
-        Node::Branch { bbox, children }
+        Node::Branch {
+            children: children as u32,
+            bbox,
+        }
     }
 
-    pub fn add(&mut self, p: Vec3) -> bool {
-        match self {
-            Node::Leaf { points, bbox } => {
-                let inserted = points.insert(p);
+    /// Same as `bulk_build`, but the branches of the node are built in
+    /// parallel by at most `available_parallelism` threads, each building its
+    /// branches sequentially. Only the top level is parallelized so that
+    /// threads are spawned once.
+    fn par_bulk_build(
+        &mut self,
+        bbox: Bbox,
+        bits: u32,
+        pts: &[(u128, Vec3)],
+        max_leaf_size: usize,
+    ) -> Node {
+        if pts.len() < PARALLEL_BULK_LOAD_SIZE || pts.len() <= max_leaf_size || bits == 0 {
+            return self.bulk_build(bbox, bits, pts, max_leaf_size);
+        }
+
+        let ranges = octant_ranges(pts, bits);
+        let sub_bboxes = split_bbox(&bbox, bbox.center());
+        let children = self.alloc_children();
+
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let branches = ranges.into_iter().zip(sub_bboxes).collect::<Vec<_>>();
+
+        // every branch is built in its own arena, then they're all merged
+        let built = std::thread::scope(|s| {
+            branches
+                .chunks(branches.len().div_ceil(threads))
+                .map(|chunk| {
+                    s.spawn(move || {
+                        chunk
+                            .iter()
+                            .map(|(range, b)| {
+                                let mut arena = Arena::default();
+                                let node =
+                                    arena.bulk_build(b.clone(), bits - 1, range, max_leaf_size);
+                                (arena, node)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .flat_map(|h| h.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+
+        for (i, (arena, node)) in built.into_iter().enumerate() {
+            self.nodes[children + i] = self.append(arena, node);
+        }
 
-                if points.len() > MAX_LEAF_SIZE {
-                    let mut t = HashSet::new();
-                    std::mem::swap(&mut t, points);
+        Node::Branch {
+            children: children as u32,
+            bbox,
+        }
+    }
 
-                    *self = Node::new(bbox.clone(), t);
-                }
+    /// Reserve room for the eight children of a branch and return the index
+    /// of the first one.
+    fn alloc_children(&mut self) -> usize {
+        let children = self.nodes.len();
+        self.nodes.resize(
+            children + 8,
+            Node::Leaf {
+                start: 0,
+                len: 0,
+                cap: 0,
+                bbox: Bbox::new(Vec3::new(0, 0, 0)),
+            },
+        );
+        children
+    }
 
-                inserted
-            }
-            Node::Branch { children, bbox } => {
-                let i = partition_pt(p, bbox.center());
-                children[i].add(p)
+    /// Move all the nodes and points of the other arena into this one and
+    /// return the given node of the other arena fixed up to point into this
+    /// one.
+    fn append(&mut self, other: Arena, node: Node) -> Node {
+        let node_offset = self.nodes.len() as u32;
+        let point_offset = self.points.len() as u32;
+
+        let relocate = |n: Node| match n {
+            Node::Branch { children, bbox } => Node::Branch {
+                children: children + node_offset,
+                bbox,
+            },
+            Node::Leaf {
+                start,
+                len,
+                cap,
+                bbox,
+            } => Node::Leaf {
+                start: start + point_offset,
+                len,
+                cap,
+                bbox,
+            },
+        };
+
+        self.nodes.extend(other.nodes.into_iter().map(relocate));
+        self.points.extend(other.points);
+
+        for (class, free) in other.free.into_iter().enumerate() {
+            for start in free {
+                self.free_points(start + point_offset, 1 << class);
             }
         }
+
+        relocate(node)
     }
 
-    pub fn nearest(&self, p: Vec3) -> Option<(Vec3, i64)> {
-        match self {
-            Node::Leaf { points, .. } => points
-                .iter()
-                .map(|pt| (*pt, pt.dist2(p)))
-                .min_by_key(|(_, d)| *d),
+    fn nearest(&self, i: usize, p: Vec3, nearest: &mut Option<(Vec3, i64)>) {
+        match &self.nodes[i] {
+            Node::Leaf { start, len, .. } => {
+                for pt in &self.points[*start as usize..(*start + *len) as usize] {
+                    let d = pt.dist2(p);
+                    if nearest.is_none_or(|(_, min)| d < min) {
+                        *nearest = Some((*pt, d));
+                    }
+                }
+            }
             Node::Branch { children, bbox } => {
-                let enclosing_bbox_id = partition_pt(p, bbox.center());
+                let children = *children as usize;
+                let enclosing = partition_pt(p, bbox.center());
 
-                let mut nearest = children[enclosing_bbox_id].nearest(p);
+                self.nearest(children + enclosing, p, nearest);
 
-                for (bbox_id, child) in children.iter().enumerate() {
-                    if bbox_id == enclosing_bbox_id {
+                for child in (children..children + 8).filter(|c| *c != children + enclosing) {
+                    if nearest.is_some_and(|(_, min)| self.nodes[child].bbox().dist2(p) >= min) {
                         continue;
                     }
 
-                    match nearest {
-                        None => {
-                            nearest = child.nearest(p);
-                        }
-                        Some((_, min_dist)) => {
-                            if child.bbox().dist2(p) >= min_dist {
-                                continue;
-                            }
-
-                            if let Some((n, d)) = child.nearest(p) {
-                                if d < min_dist {
-                                    nearest = Some((n, d));
-                                }
-                            }
-                        }
-                    }
+                    self.nearest(child, p, nearest);
                 }
-
-                nearest
             }
         }
     }
 
-    pub fn bbox(&self) -> &Bbox {
-        match self {
-            Node::Branch { bbox, .. } | Node::Leaf { bbox, .. } => bbox,
+    fn within(&self, i: usize, p: Vec3, radius2: i64, out: &mut Vec<Vec3>) {
+        match &self.nodes[i] {
+            Node::Leaf { bbox, .. } if bbox.dist2(p) > radius2 => {}
+            Node::Branch { bbox, .. } if bbox.dist2(p) > radius2 => {}
+            Node::Leaf { start, len, .. } => {
+                let pts = &self.points[*start as usize..(*start + *len) as usize];
+                out.extend(pts.iter().filter(|pt| pt.dist2(p) <= radius2));
+            }
+            Node::Branch { children, .. } => {
+                let children = *children as usize;
+                for child in children..children + 8 {
+                    self.within(child, p, radius2, out);
+                }
+            }
         }
     }
 }
 
-impl<'o> Iterator for OctreeIter<'o> {
-    type Item = &'o Vec3;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if let Some(p) = self.current.as_mut().and_then(|c| c.next()) {
-                self.len -= 1;
-                break Some(p);
-            }
-
-            let n = self.stack.pop()?;
-
-            match n {
-                Node::Branch { children, .. } => {
-                    self.stack.extend(children.iter());
-                }
-                Node::Leaf { points, .. } => {
-                    self.current = Some(points.iter());
-                }
-            }
+impl Node {
+    fn bbox(&self) -> &Bbox {
+        match self {
+            Node::Branch { bbox, .. } | Node::Leaf { bbox, .. } => bbox,
         }
     }
+}
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        (self.len, Some(self.len))
+impl PartialEq for Octree {
+    fn eq(&self, other: &Self) -> bool {
+        self.len == other.len
+            && self
+                .iter()
+                .all(|p| other.nearest(*p).is_some_and(|(_, d)| d == 0))
     }
 }
 
+impl Eq for Octree {}
+
 impl std::iter::FromIterator<Vec3> for Octree {
     fn from_iter<T: IntoIterator<Item = Vec3>>(iter: T) -> Self {
-        let pts = iter.into_iter().collect::<HashSet<_>>();
+        Octree::bulk_load(iter)
+    }
+}
 
-        let bbox = {
-            let mut pts = pts.iter();
+impl Extend<Vec3> for Octree {
+    fn extend<T: IntoIterator<Item = Vec3>>(&mut self, iter: T) {
+        if self.is_empty() {
+            *self = Octree::with_leaf_size(self.max_leaf_size).bulk_loaded(iter);
+            return;
+        }
 
-            let bbox = match pts.next() {
-                None => return Octree::new(),
-                Some(first) => Bbox::new(*first),
-            };
+        let pts = iter.into_iter().collect::<Vec<_>>();
 
-            pts.fold(bbox, |b, p| b.expand(*p))
-        };
+        // grow the root once to fit all the points instead of little by little
+        if let Some((first, rest)) = pts.split_first() {
+            let bbox = rest.iter().fold(Bbox::new(*first), |b, p| b.expand(*p));
+            self.grow(bbox.lower());
+            self.grow(bbox.upper());
+        }
 
-        let len = pts.len();
-        Octree {
-            outside: HashSet::with_capacity(MAX_LEAF_SIZE),
-            root: Some(Node::new(bbox, pts)),
-            len,
-            rebuilt_count: 0,
+        for p in pts {
+            self.add(p);
         }
     }
 }
@@ -323,6 +776,58 @@ fn split_bbox(bbox: &Bbox, c: Vec3) -> [Bbox; 8] {
     ]
 }
 
+/// Whether the node with the given bbox owns the point.
+fn owns(bbox: &Bbox, p: Vec3) -> bool {
+    let (l, u) = (bbox.lower(), bbox.upper());
+    l.x < p.x && p.x <= u.x && l.y < p.y && p.y <= u.y && l.z < p.z && p.z <= u.z
+}
+
+/// Split points sorted by their Morton code relative to a cube of side
+/// `2^bits` among the eight octants of the cube.
+fn octant_ranges(pts: &[(u128, Vec3)], bits: u32) -> [&[(u128, Vec3)]; 8] {
+    let shift = 3 * (bits - 1);
+    let octant = |code: u128| (code >> shift) as usize & 7;
+
+    let mut ranges = [&pts[..0]; 8];
+    let mut rest = pts;
+    for (i, range) in ranges.iter_mut().enumerate() {
+        let n = rest.partition_point(|(code, _)| octant(*code) <= i);
+        (*range, rest) = rest.split_at(n);
+    }
+
+    ranges
+}
+
+/// Morton codes of the points relative to `origin`, the bits are interleaved
+/// so that the octant of a code matches the child index of `partition_pt`.
+fn morton_codes(pts: &[Vec3], origin: Vec3, bits: u32) -> Vec<(u128, Vec3)> {
+    let code = |p: &Vec3| {
+        let q = *p - origin;
+        let mut code = 0_u128;
+        for b in 0..bits {
+            let bit = |v: i64| u128::from((v >> b) & 1 == 1);
+            code |= (bit(q.y) << 2 | bit(q.x) << 1 | bit(q.z)) << (3 * b);
+        }
+        (code, *p)
+    };
+
+    if pts.len() < PARALLEL_BULK_LOAD_SIZE {
+        return pts.iter().map(code).collect();
+    }
+
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let chunk = pts.len().div_ceil(threads);
+
+    std::thread::scope(|s| {
+        pts.chunks(chunk)
+            .map(|c| s.spawn(move || c.iter().map(code).collect::<Vec<_>>()))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .flat_map(|h| h.join().unwrap())
+            .collect()
+    })
+}
+
 fn partition_pt(p: Vec3, c: Vec3) -> usize {
     if p.x <= c.x && p.y <= c.y && p.z <= c.z {
         0
@@ -351,10 +856,48 @@ impl Default for Octree {
     }
 }
 
+/// Octrees are serialized as their leaf size and their points, the nodes are
+/// rebuilt when deserializing.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Octree {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        struct Points<'o>(&'o Octree);
+
+        impl serde::Serialize for Points<'_> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq(self.0.iter())
+            }
+        }
+
+        let mut s = serializer.serialize_struct("Octree", 2)?;
+        s.serialize_field("max_leaf_size", &self.max_leaf_size)?;
+        s.serialize_field("points", &Points(self))?;
+        s.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Octree {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        struct OctreeData {
+            max_leaf_size: usize,
+            points: Vec<Vec3>,
+        }
+
+        let data = OctreeData::deserialize(deserializer)?;
+        Ok(Octree::with_leaf_size(data.max_leaf_size).bulk_loaded(data.points))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use hashbrown::HashSet;
+
     use proptest::prelude::*;
 
     proptest! {
@@ -372,5 +915,88 @@ mod tests {
             let nn2 = pts.iter().map(|pp| pp.dist2(p)).min();
             prop_assert_eq!(nn, nn2);
         }
+
+        #[test]
+        fn points_within_are_the_same_as_iterating(
+            pts in prop::collection::hash_set((-300_i64..300, -300_i64..300, -300_i64..300), 1000),
+            p in (-300_i64..300, -300_i64..300, -300_i64..300),
+            radius in 0_i64..200,
+        ) {
+            let pts: HashSet<_> = pts.into_iter().map(|(x,y,z)| Vec3::new(x,y,z)).collect();
+            let p = Vec3::new(p.0, p.1, p.2);
+
+            let octree: Octree = pts.clone().into_iter().collect();
+
+            let within = octree.within(p, radius * radius);
+            let expected: HashSet<_> = pts.iter().filter(|pp| pp.dist2(p) <= radius * radius).copied().collect();
+            prop_assert_eq!(within.len(), expected.len());
+            prop_assert_eq!(within.into_iter().collect::<HashSet<_>>(), expected);
+        }
+
+        #[test]
+        fn bulk_load_agrees_with_incremental_insertion(
+            pts in prop::collection::vec((-300_i64..300, -300_i64..300, -300_i64..300), 0..3000),
+        ) {
+            let pts: Vec<_> = pts.into_iter().map(|(x,y,z)| Vec3::new(x,y,z)).collect();
+            let unique: HashSet<_> = pts.iter().copied().collect();
+
+            let mut bulk: Octree = pts.iter().copied().collect();
+
+            let mut incremental = Octree::with_leaf_size(4);
+            for p in &pts {
+                incremental.add(*p);
+            }
+
+            let (head, tail) = pts.split_at(pts.len() / 3);
+            let mut extended: Octree = head.iter().copied().collect();
+            extended.extend(tail.iter().copied());
+
+            prop_assert_eq!(bulk.len(), unique.len());
+            prop_assert_eq!(incremental.len(), unique.len());
+            prop_assert_eq!(&incremental, &bulk);
+            prop_assert!(incremental.memory_usage().point_slots >= unique.len());
+
+            let stats = incremental.stats();
+            prop_assert_eq!(stats.leaves + stats.branches, incremental.memory_usage().nodes);
+            prop_assert_eq!(stats.depth_histogram.iter().sum::<usize>(), stats.leaves);
+            prop_assert_eq!(
+                stats.leaf_occupancy.iter().enumerate().map(|(n, l)| n * l).sum::<usize>(),
+                unique.len()
+            );
+            prop_assert_eq!(extended.len(), unique.len());
+            prop_assert_eq!(bulk.iter().copied().collect::<HashSet<_>>(), unique.clone());
+
+            // points must be found where they would have been added
+            for p in &pts {
+                bulk.add(*p);
+                prop_assert_eq!(extended.nearest(*p).map(|(_, d)| d), Some(0));
+            }
+            prop_assert_eq!(bulk.len(), unique.len());
+        }
+    }
+
+    #[test]
+    fn bulk_load_in_parallel() {
+        let pts = (0..100_000_i64)
+            .map(|i| Vec3::new(i % 97 * 31, i / 97 % 101 - 50, i % 13 * -1000))
+            .collect::<Vec<_>>();
+        let unique = pts.iter().copied().collect::<HashSet<_>>();
+
+        let mut octree = Octree::bulk_load(pts.iter().copied());
+        assert_eq!(octree.len(), unique.len());
+
+        let mut incremental = Octree::new();
+        for p in &pts {
+            incremental.add(*p);
+        }
+        assert_eq!(octree, incremental);
+
+        for p in pts.iter().step_by(7) {
+            octree.add(*p);
+        }
+        octree.add(Vec3::new(-1_000_000, 0, 0));
+
+        assert_eq!(octree.len(), unique.len() + 1);
+        assert!(octree.rebuilt_count() > 0);
     }
 }
@@ -0,0 +1,307 @@
+use hashbrown::HashMap;
+use rand::Rng;
+
+use crate::geo::{Ray, Sphere, Vec3, Vec3f, MAX_COORD};
+use crate::growth::random_unit_vector;
+use crate::octree::Octree;
+
+// number of cubes of the grid along a particle radius
+const CUBES_PER_RADIUS: f64 = 8.0;
+
+/// Diffusion limited aggregation where particles are spheres free to move
+/// anywhere in space instead of being constrained to an integer lattice.
+///
+/// Walkers stick as soon as they touch a particle of the aggregate and the
+/// contact point is computed exactly, hence all the particles are tangent to at
+/// least another one.
+///
+/// The particles are indexed by the cube of a fine grid their center falls
+/// in, so that the lattice `Octree` of the occupied cubes answers the spatial
+/// queries instead of a dedicated octree of floating point points. Cubes are
+/// an eighth of the particle radius wide, hence each cube holds at most one
+/// particle since particles never overlap.
+///
+/// The grid only prunes the queries: `within` and the contact points are
+/// computed on the exact positions, while the distance the walkers jump is
+/// underestimated by at most a cube diagonal, about a fifth of the particle
+/// radius. Besides the positions, each particle takes a point in the octree
+/// and an entry in a hash map from its cube to its index, roughly 40 more
+/// bytes per particle. The grid limits the positions to `MAX_COORD` cubes,
+/// that is `MAX_COORD / 8` particle radii, from the origin.
+///
+/// It's only available as a library since the scene formats, the native dump
+/// and the `simulate` subcommand all deal with lattice cells, and rounding the
+/// particles to the lattice would throw away their exact contact points.
+#[derive(Debug, Clone)]
+pub struct OffLatticeDla {
+    particle_radius: f64,
+    spawn_radius: f64,
+    cells: Vec<Vec3f>,
+    cubes: Octree,
+    // index of the cell falling in each occupied cube
+    owners: HashMap<Vec3, usize>,
+    center: Vec3f,
+    radius: f64,
+}
+
+impl OffLatticeDla {
+    /// Create a new off-lattice DLA made of particles with the given radius
+    /// that initially contains the given seeds. Walkers are spawned
+    /// `spawn_radius` away from the aggregate. Return `None` if there are no
+    /// seeds, if the radii are not positive, if a seed is not finite or too
+    /// far from the origin, or if two seeds overlap.
+    pub fn new(
+        particle_radius: f64,
+        spawn_radius: f64,
+        seeds: impl IntoIterator<Item = Vec3f>,
+    ) -> Option<Self> {
+        let valid = |r: f64| r > 0.0 && r.is_finite();
+        if !valid(particle_radius) || !valid(spawn_radius) {
+            return None;
+        }
+
+        let seeds = seeds.into_iter().collect::<Vec<_>>();
+        if seeds.is_empty() {
+            return None;
+        }
+
+        let center = seeds.iter().fold(Vec3f::default(), |c, p| c + *p) / seeds.len() as f64;
+        let radius = seeds.iter().map(|p| p.dist(center)).fold(0.0, f64::max);
+
+        let mut dla = OffLatticeDla {
+            particle_radius,
+            spawn_radius,
+            cells: vec![],
+            cubes: Octree::new(),
+            owners: HashMap::new(),
+            center,
+            radius,
+        };
+        let contact = 2.0 * particle_radius;
+        for p in seeds {
+            if !p.is_finite() || !dla.cube(p).is_bounded() {
+                return None;
+            }
+            // tangent seeds are fine, just like the particles stuck later
+            if dla
+                .within(p, contact)
+                .iter()
+                .any(|c| c.dist2(p) < contact.powi(2))
+            {
+                return None;
+            }
+
+            dla.insert(p);
+        }
+
+        Some(dla)
+    }
+
+    pub fn particle_radius(&self) -> f64 {
+        self.particle_radius
+    }
+
+    pub fn spawn_radius(&self) -> f64 {
+        self.spawn_radius
+    }
+
+    pub fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The centers of all the particles in the aggregate, in the order they
+    /// were added.
+    pub fn cells(&self) -> impl Iterator<Item = &Vec3f> {
+        self.cells.iter()
+    }
+
+    /// The octree of the cubes of the grid that contain at least a particle,
+    /// see `OffLatticeDla::cube`.
+    pub fn octree(&self) -> &Octree {
+        &self.cubes
+    }
+
+    /// The cube of the grid containing `p`.
+    pub fn cube(&self, p: Vec3f) -> Vec3 {
+        (p / self.cube_size()).floor()
+    }
+
+    fn cube_size(&self) -> f64 {
+        self.particle_radius / CUBES_PER_RADIUS
+    }
+
+    /// All the particles whose center is at most `radius` away from `p`.
+    pub fn within(&self, p: Vec3f, radius: f64) -> Vec<Vec3f> {
+        if radius.is_nan() || radius < 0.0 {
+            return vec![];
+        }
+
+        // points in different cubes can be up to a cube diagonal closer than
+        // their cubes
+        let cubes = (radius / self.cube_size() + 3.0_f64.sqrt()).ceil();
+        let cube = self.cube(p);
+
+        // the octree can't answer queries from beyond `MAX_COORD` or spanning
+        // more than the whole grid without overflowing, but then all the
+        // particles are candidates anyway
+        if !cube.is_bounded() || cubes > 4.0 * MAX_COORD as f64 {
+            return self
+                .cells
+                .iter()
+                .copied()
+                .filter(|c| c.dist2(p) <= radius.powi(2))
+                .collect();
+        }
+
+        self.cubes
+            .within(cube, (cubes as i64).pow(2))
+            .into_iter()
+            .map(|c| self.cells[self.owners[&c]])
+            .filter(|c| c.dist2(p) <= radius.powi(2))
+            .collect()
+    }
+
+    /// Distance between the center of the seeds and the farthest particle.
+    pub fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    /// Launch a walker and let it diffuse until it touches the aggregate,
+    /// return the position where it stuck.
+    pub fn add<R: Rng>(&mut self, rng: &mut R) -> Vec3f {
+        let contact = 2.0 * self.particle_radius;
+        let spawn = self.radius + contact + self.spawn_radius;
+        let kill = spawn * 2.0;
+
+        let mut p = self.center + random_unit_vector(rng) * spawn;
+
+        loop {
+            // when the walker is far from the aggregate it can safely jump to
+            // any point of the sphere that doesn't touch any particle
+            let step = (self.min_dist(p) - contact).max(self.particle_radius);
+            let dir = random_unit_vector(rng);
+
+            if let Some(t) = self.first_contact(p, dir, step) {
+                let cell = p + dir * t;
+                self.insert(cell);
+                self.radius = self.radius.max(cell.dist(self.center));
+
+                return cell;
+            }
+
+            p = p + dir * step;
+            if p.dist2(self.center) > kill.powi(2) {
+                p = self.center + random_unit_vector(rng) * spawn;
+            }
+        }
+    }
+
+    fn insert(&mut self, p: Vec3f) {
+        let cube = self.cube(p);
+        self.cubes.add(cube);
+        self.owners.insert(cube, self.cells.len());
+        self.cells.push(p);
+    }
+
+    /// A lower bound of the distance between `p` and the closest particle,
+    /// the closest particle can be up to a cube diagonal closer than its
+    /// cube.
+    fn min_dist(&self, p: Vec3f) -> f64 {
+        let (_, d2) = self.cubes.nearest(self.cube(p)).unwrap();
+        let cubes = ((d2 as f64).sqrt() - 3.0_f64.sqrt()).max(0.0);
+
+        cubes * self.cube_size()
+    }
+
+    /// Distance along the segment starting from `p` with direction `dir` and
+    /// length `len` at which a walker first touches a particle, if any.
+    fn first_contact(&self, p: Vec3f, dir: Vec3f, len: f64) -> Option<f64> {
+        let contact = 2.0 * self.particle_radius;
+
+        let ray = Ray::new(p, dir);
+
+        self.within(p, len + contact)
+            .into_iter()
+            .filter_map(|c| ray.intersect_sphere(&Sphere::new(c, contact)))
+            .filter(|t| *t <= len)
+            .min_by(|a, b| a.total_cmp(b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn particles_touch_without_overlapping() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = OffLatticeDla::new(0.5, 5.0, vec![Vec3f::default()]).unwrap();
+
+        let mut cells = vec![Vec3f::default()];
+        for _ in 0..300 {
+            let c = dla.add(&mut rng);
+
+            let closest = cells
+                .iter()
+                .map(|o| o.dist(c))
+                .fold(f64::INFINITY, f64::min);
+            assert!((closest - 1.0).abs() < 1e-9, "{}", closest);
+
+            let close = dla.within(c + Vec3f::new(0.1, 0.0, 0.0), 0.1 + 1e-9);
+            assert!(close.contains(&c));
+            assert!(dla
+                .octree()
+                .nearest(dla.cube(c))
+                .is_some_and(|(_, d2)| d2 == 0));
+
+            cells.push(c);
+        }
+
+        assert_eq!(dla.len(), 301);
+        assert!(dla.cells().eq(cells.iter()));
+
+        let mut found = dla.within(Vec3f::default(), 40.0);
+        found.sort_by(|a, b| a.x.total_cmp(&b.x));
+        cells.sort_by(|a, b| a.x.total_cmp(&b.x));
+        assert_eq!(found, cells);
+    }
+
+    #[test]
+    fn queries_of_any_size_are_answered() {
+        let seeds = vec![Vec3f::default(), Vec3f::new(1.0, 0.0, 0.0)];
+        let dla = OffLatticeDla::new(0.5, 5.0, seeds.clone()).unwrap();
+
+        for radius in [1e12, f64::MAX, f64::INFINITY] {
+            assert_eq!(dla.within(Vec3f::default(), radius), seeds);
+        }
+        assert_eq!(dla.within(Vec3f::new(1e30, 0.0, 0.0), 1e30), seeds);
+        assert!(dla.within(Vec3f::new(1e30, 0.0, 0.0), 1.0).is_empty());
+        assert!(dla.within(Vec3f::default(), -1.0).is_empty());
+        assert!(dla.within(Vec3f::default(), f64::NAN).is_empty());
+        assert!(dla.within(Vec3f::new(f64::NAN, 0.0, 0.0), 1.0).is_empty());
+    }
+
+    #[test]
+    fn invalid_seeds_are_rejected() {
+        let new = |seeds: Vec<Vec3f>| OffLatticeDla::new(0.5, 5.0, seeds);
+        let origin = Vec3f::default();
+
+        assert!(new(vec![]).is_none());
+        assert!(new(vec![Vec3f::new(f64::NAN, 0.0, 0.0)]).is_none());
+        assert!(new(vec![Vec3f::new(0.0, f64::INFINITY, 0.0)]).is_none());
+        assert!(new(vec![Vec3f::new(0.0, 0.0, MAX_COORD as f64)]).is_none());
+        assert!(new(vec![origin, origin]).is_none());
+        assert!(new(vec![origin, Vec3f::new(0.0, 0.999, 0.0)]).is_none());
+        assert!(OffLatticeDla::new(0.0, 5.0, vec![origin]).is_none());
+
+        let tangent = new(vec![origin, Vec3f::new(0.0, 1.0, 0.0)]).unwrap();
+        assert_eq!(tangent.len(), 2);
+        assert_eq!(tangent.within(origin, 1.0).len(), 2);
+    }
+}
@@ -0,0 +1,469 @@
+use std::{
+    fmt,
+    io::{self, BufRead},
+    sync::Arc,
+};
+
+use crate::geo::{Axis, Bbox, Vec3, Vec3f};
+use crate::DlaError;
+
+/// A solid region of space used to constrain where walkers can move and where
+/// particles can stick.
+#[derive(Debug, Clone)]
+pub enum Shape {
+    /// An axis aligned box.
+    Box(Bbox),
+
+    /// A sphere centered in `center` with the given `radius`.
+    Sphere { center: Vec3, radius: i64 },
+
+    /// An axis aligned cylinder whose base is centered in `base` and that
+    /// extends for `height` along `axis`.
+    Cylinder {
+        base: Vec3,
+        axis: Axis,
+        radius: i64,
+        height: i64,
+    },
+
+    /// A closed triangle mesh.
+    Mesh(Mesh),
+
+    /// A shape defined by its signed distance function, negative inside the
+    /// shape and positive outside.
+    Sdf(Sdf),
+}
+
+/// A closed triangle mesh, usually loaded from an OBJ file.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    triangles: Vec<[Vec3f; 3]>,
+    bbox: Bbox,
+}
+
+/// A signed distance function.
+#[derive(Clone)]
+pub struct Sdf(Arc<dyn Fn(Vec3) -> f64 + Send + Sync>);
+
+impl Shape {
+    /// Signed distance between the shape and the given point, negative when
+    /// the point is inside the shape.
+    pub fn distance(&self, p: Vec3) -> f64 {
+        match self {
+            Shape::Box(bbox) => {
+                let lower = Vec3f::from(bbox.lower());
+                let upper = Vec3f::from(bbox.upper());
+
+                let center = (lower + upper) / 2.0;
+                let half = (upper - lower) / 2.0;
+
+                let q = (Vec3f::from(p) - center).abs() - half;
+                q.max(Vec3f::default()).norm() + q.x.max(q.y).max(q.z).min(0.0)
+            }
+            Shape::Sphere { center, radius } => {
+                Vec3f::from(p).dist(Vec3f::from(*center)) - *radius as f64
+            }
+            Shape::Cylinder {
+                base,
+                axis,
+                radius,
+                height,
+            } => {
+                let d = Vec3f::from(p - *base);
+                let (h, u, v) = match axis {
+                    Axis::X => (d.x, d.y, d.z),
+                    Axis::Y => (d.y, d.x, d.z),
+                    Axis::Z => (d.z, d.x, d.y),
+                };
+
+                let half_height = *height as f64 / 2.0;
+                let dr = u.hypot(v) - *radius as f64;
+                let dh = (h - half_height).abs() - half_height;
+
+                dr.max(0.0).hypot(dh.max(0.0)) + dr.max(dh).min(0.0)
+            }
+            Shape::Mesh(mesh) => mesh.distance(p),
+            Shape::Sdf(sdf) => (sdf.0)(p),
+        }
+    }
+
+    /// Check that the sizes of the shape are not negative, a shape with a
+    /// negative size contains no point.
+    pub fn validate(&self) -> Result<(), DlaError> {
+        let sizes = match self {
+            Shape::Sphere { radius, .. } => vec![("radius", *radius)],
+            Shape::Cylinder { radius, height, .. } => {
+                vec![("radius", *radius), ("height", *height)]
+            }
+            Shape::Box(_) | Shape::Mesh(_) | Shape::Sdf(_) => vec![],
+        };
+
+        match sizes.into_iter().find(|(_, value)| *value < 0) {
+            Some((size, value)) => Err(DlaError::NegativeShapeSize { size, value }),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether the given point is inside the shape or on its boundary.
+    pub fn contains(&self, p: Vec3) -> bool {
+        match self {
+            Shape::Box(bbox) => bbox.contains(p),
+            Shape::Sphere { center, radius } => {
+                *radius >= 0 && p.dist2_wide(*center) <= (*radius as u128).pow(2)
+            }
+            Shape::Mesh(mesh) => mesh.contains(p),
+            Shape::Cylinder { .. } | Shape::Sdf(_) => self.distance(p) <= 0.0,
+        }
+    }
+}
+
+impl Mesh {
+    /// Create a mesh from the given triangles. Return `None` if there are no
+    /// triangles or if a vertex is not finite.
+    pub fn new(triangles: Vec<[Vec3f; 3]>) -> Option<Self> {
+        if !triangles.iter().flatten().all(Vec3f::is_finite) {
+            return None;
+        }
+
+        let mut pts = triangles.iter().flatten();
+
+        let first = pts.next()?;
+        let (lower, upper) = pts.fold((*first, *first), |(l, u), p| (l.min(*p), u.max(*p)));
+
+        let bbox = Bbox::new(Vec3::new(
+            lower.x.floor() as i64,
+            lower.y.floor() as i64,
+            lower.z.floor() as i64,
+        ))
+        .expand(Vec3::new(
+            upper.x.ceil() as i64,
+            upper.y.ceil() as i64,
+            upper.z.ceil() as i64,
+        ));
+
+        Some(Mesh { triangles, bbox })
+    }
+
+    /// Load a mesh from an OBJ file. Only vertices and faces are taken into
+    /// account, polygonal faces are split into triangles.
+    pub fn from_obj(r: impl BufRead) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+        let mut vertices = vec![];
+        let mut triangles = vec![];
+
+        for (i, line) in r.lines().enumerate() {
+            let line = line?;
+            let mut tokens = line.split_whitespace();
+
+            match tokens.next() {
+                Some("v") => {
+                    let coords = tokens
+                        .take(3)
+                        .map(|t| t.parse::<f64>())
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(|e| invalid(format!("line {}: invalid vertex: {}", i + 1, e)))?;
+
+                    if coords.len() != 3 {
+                        return Err(invalid(format!("line {}: vertex without 3 coords", i + 1)));
+                    }
+                    if !coords.iter().all(|c| c.is_finite()) {
+                        return Err(invalid(format!("line {}: vertex is not finite", i + 1)));
+                    }
+
+                    vertices.push(Vec3f::new(coords[0], coords[1], coords[2]));
+                }
+                Some("f") => {
+                    let face = tokens
+                        .map(|t| {
+                            // faces can be in the form `v`, `v/vt`, `v//vn` or
+                            // `v/vt/vn`, only `v` is interesting
+                            let ix = t.split('/').next().unwrap_or(t);
+                            let ix = ix.parse::<i64>().map_err(|e| {
+                                invalid(format!("line {}: invalid face: {}", i + 1, e))
+                            })?;
+
+                            // negative indices are relative to the end
+                            let ix = if ix < 0 {
+                                vertices.len() as i64 + ix
+                            } else {
+                                ix - 1
+                            };
+
+                            usize::try_from(ix)
+                                .ok()
+                                .and_then(|ix| vertices.get(ix).copied())
+                                .ok_or_else(|| {
+                                    invalid(format!("line {}: face vertex out of range", i + 1))
+                                })
+                        })
+                        .collect::<io::Result<Vec<_>>>()?;
+
+                    for w in 1..face.len().saturating_sub(1) {
+                        triangles.push([face[0], face[w], face[w + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Mesh::new(triangles).ok_or_else(|| invalid("the mesh has no faces".to_string()))
+    }
+
+    pub fn triangles(&self) -> &[[Vec3f; 3]] {
+        &self.triangles
+    }
+
+    pub fn bbox(&self) -> &Bbox {
+        &self.bbox
+    }
+
+    /// Whether the point is inside the mesh, assuming it's closed.
+    pub fn contains(&self, p: Vec3) -> bool {
+        if !self.bbox.contains(p) {
+            return false;
+        }
+
+        // count how many times a ray starting from the point crosses the mesh,
+        // the direction is slightly skewed to avoid hitting edges exactly
+        let origin = Vec3f::from(p);
+        let dir = Vec3f::new(1.0, 0.001_234, 0.000_567);
+
+        let hits = self
+            .triangles
+            .iter()
+            .filter(|t| ray_triangle_intersection(origin, dir, t).is_some())
+            .count();
+
+        hits % 2 == 1
+    }
+
+    /// Signed distance between the point and the mesh, negative inside.
+    pub fn distance(&self, p: Vec3) -> f64 {
+        let pf = Vec3f::from(p);
+
+        let d = self
+            .triangles
+            .iter()
+            .map(|t| pf.dist(closest_point_on_triangle(pf, t)))
+            .fold(f64::INFINITY, f64::min);
+
+        if self.contains(p) {
+            -d
+        } else {
+            d
+        }
+    }
+}
+
+impl Sdf {
+    pub fn new(f: impl Fn(Vec3) -> f64 + Send + Sync + 'static) -> Self {
+        Sdf(Arc::new(f))
+    }
+}
+
+impl fmt::Debug for Sdf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Sdf(..)")
+    }
+}
+
+/// Möller–Trumbore ray triangle intersection, return the distance along the
+/// ray of the intersection point, if any.
+fn ray_triangle_intersection(origin: Vec3f, dir: Vec3f, [a, b, c]: &[Vec3f; 3]) -> Option<f64> {
+    let e1 = *b - *a;
+    let e2 = *c - *a;
+
+    let p = dir.cross(e2);
+    let det = e1.dot(p);
+    if det.abs() < f64::EPSILON {
+        return None;
+    }
+
+    let inv_det = 1.0 / det;
+    let t = origin - *a;
+
+    let u = t.dot(p) * inv_det;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = t.cross(e1);
+    let v = dir.dot(q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = e2.dot(q) * inv_det;
+    if t > 0.0 {
+        Some(t)
+    } else {
+        None
+    }
+}
+
+/// Closest point to `p` on the given triangle, see "Real-Time Collision
+/// Detection" by Christer Ericson.
+fn closest_point_on_triangle(p: Vec3f, [a, b, c]: &[Vec3f; 3]) -> Vec3f {
+    let (a, b, c) = (*a, *b, *c);
+
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denom = 1.0 / (va + vb + vc);
+    let v = vb * denom;
+    let w = vc * denom;
+    a + ab * v + ac * w
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUBE_OBJ: &str = "
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 2 3 7 6
+f 3 4 8 7
+f 4 1 5 8
+";
+
+    #[test]
+    fn shapes_agree_with_their_distance() {
+        let mesh = Mesh::from_obj(CUBE_OBJ.as_bytes()).unwrap();
+        let mesh = Shape::Mesh(
+            Mesh::new(
+                mesh.triangles()
+                    .iter()
+                    .map(|t| t.map(|p| p * 10.0))
+                    .collect(),
+            )
+            .unwrap(),
+        );
+
+        let shapes = [
+            Shape::Box(Bbox::new(Vec3::new(-10, -10, -10)).expand(Vec3::new(10, 10, 10))),
+            Shape::Sphere {
+                center: Vec3::new(0, 0, 0),
+                radius: 10,
+            },
+            Shape::Cylinder {
+                base: Vec3::new(0, -10, 0),
+                axis: Axis::Y,
+                radius: 10,
+                height: 20,
+            },
+            mesh,
+            Shape::Sdf(Sdf::new(|p| Vec3f::from(p).norm() - 10.0)),
+        ];
+
+        for shape in &shapes {
+            assert!(shape.contains(Vec3::new(0, 0, 0)), "{:?}", shape);
+            assert!(shape.contains(Vec3::new(0, 9, 0)), "{:?}", shape);
+            assert!(!shape.contains(Vec3::new(0, 11, 0)), "{:?}", shape);
+            assert!(!shape.contains(Vec3::new(20, 20, 20)), "{:?}", shape);
+
+            assert!(shape.distance(Vec3::new(0, 0, 0)) < 0.0, "{:?}", shape);
+            assert!(
+                (shape.distance(Vec3::new(0, 15, 0)) - 5.0).abs() < 1e-9,
+                "{:?}",
+                shape
+            );
+        }
+    }
+
+    #[test]
+    fn negative_sizes_are_rejected() {
+        let origin = Vec3::new(0, 0, 0);
+        let sphere = Shape::Sphere {
+            center: origin,
+            radius: -5,
+        };
+        let cylinder = |radius, height| Shape::Cylinder {
+            base: origin,
+            axis: Axis::Z,
+            radius,
+            height,
+        };
+
+        assert_eq!(
+            sphere.validate(),
+            Err(DlaError::NegativeShapeSize {
+                size: "radius",
+                value: -5
+            })
+        );
+        assert_eq!(
+            cylinder(1, -2).validate(),
+            Err(DlaError::NegativeShapeSize {
+                size: "height",
+                value: -2
+            })
+        );
+        assert!(cylinder(-1, 2).validate().is_err());
+        assert!(cylinder(0, 0).validate().is_ok());
+
+        // shapes with negative sizes are empty whichever way they're checked
+        for shape in [sphere, cylinder(-1, 2), cylinder(1, -2)] {
+            assert!(!shape.contains(origin), "{:?}", shape);
+            assert!(shape.distance(origin) > 0.0, "{:?}", shape);
+        }
+    }
+
+    #[test]
+    fn meshes_must_be_finite() {
+        let nan = Vec3f::new(f64::NAN, 0.0, 0.0);
+        let p = Vec3f::default();
+        assert!(Mesh::new(vec![[p, p, nan]]).is_none());
+        assert!(Mesh::new(vec![]).is_none());
+
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 inf 0\nf 1 2 3\n";
+        let err = Mesh::from_obj(obj.as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("line 3"), "{}", err);
+    }
+}
@@ -0,0 +1,41 @@
+/// A kind of particle with its own rules about how it moves and sticks to the
+/// other particles of the DLA.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Species {
+    /// The attraction radius of the walkers of this species, it also controls
+    /// how far they move at each step.
+    pub attraction_radius: u16,
+
+    /// Probability, between 0 and 1, that a walker of this species sticks to
+    /// a cell of another species, indexed by the id of the other species.
+    /// Missing entries are considered to be 1.
+    pub stickiness: Vec<f64>,
+
+    /// Relative frequency with which walkers of this species are spawned
+    /// compared to the other species.
+    pub spawn_ratio: f64,
+}
+
+/// Identifier of a species, it's its index in the species of the DLA.
+pub type SpeciesId = usize;
+
+/// The maximum number of species of a DLA, mostly to catch corrupted inputs
+/// that would otherwise allocate a species for every possible id.
+pub const MAX_SPECIES: usize = 256;
+
+impl Species {
+    pub fn new(attraction_radius: u16) -> Self {
+        Species {
+            attraction_radius,
+            stickiness: vec![],
+            spawn_ratio: 1.0,
+        }
+    }
+
+    /// Probability that a walker of this species sticks to a cell of the given
+    /// species.
+    pub fn stickiness_to(&self, other: SpeciesId) -> f64 {
+        self.stickiness.get(other).copied().unwrap_or(1.0)
+    }
+}
@@ -0,0 +1,292 @@
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use crate::geo::{Axis, Vec3};
+use crate::{Dla, DlaError, Event, Observer, WalkerState};
+
+/// A criterion that tells when a DLA has grown enough. See `Dla::run_until`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StopCondition {
+    /// Stop once the DLA contains at least the given number of cells.
+    Particles(usize),
+
+    /// Stop once the largest dimension of the bounding box of the DLA is at
+    /// least the given extent.
+    MaxExtent(i64),
+
+    /// Stop once the DLA touches the plane perpendicular to `axis` that
+    /// passes through `value`.
+    Plane { axis: Axis, value: i64 },
+
+    /// Stop once a cell of the DLA is at least `radius` away from `center`.
+    Sphere { center: Vec3, radius: i64 },
+
+    /// Stop once the simulation has been running for the given amount of
+    /// time.
+    Timeout(Duration),
+
+    /// Stop once the mass-radius estimate of the fractal dimension of the DLA
+    /// reaches the given value, either from above or from below. The estimate
+    /// is only considered once the DLA has at least
+    /// `FRACTAL_DIMENSION_MIN_CELLS` cells since it's too noisy before that.
+    FractalDimension(f64),
+}
+
+/// Minimum number of cells the DLA must have before its fractal dimension
+/// estimate is checked against the `FractalDimension` stop condition.
+pub const FRACTAL_DIMENSION_MIN_CELLS: usize = 100;
+
+/// How many steps a walker can take before checking whether the simulation
+/// timed out.
+const TIMEOUT_CHECK_STEPS: u64 = 1024;
+
+impl Dla {
+    /// Keep adding particles to the DLA until any of the given conditions is
+    /// met and return it. If no conditions are given, nothing is done and
+    /// `None` is returned. It fails if a walker cannot be spawned, see
+    /// `Dla::add`.
+    ///
+    /// Note that there's no implicit limit on the number of particles: if
+    /// only geometric conditions are given and the DLA can never meet them,
+    /// e.g. a plane outside of a container, this never returns. Add a
+    /// `Particles` or `Timeout` condition to bound the run.
+    pub fn run_until<'c, R: Rng>(
+        &mut self,
+        rng: &mut R,
+        conditions: &'c [StopCondition],
+    ) -> Result<Option<&'c StopCondition>, DlaError> {
+        self.run_until_with(rng, conditions, &mut |_: &Event| {})
+    }
+
+    /// Same as `run_until`, but notify the given observer about what happens
+    /// while the DLA grows. The run also stops, returning `None`, as soon as
+    /// the observer is interrupted.
+    pub fn run_until_with<'c, R: Rng>(
+        &mut self,
+        rng: &mut R,
+        conditions: &'c [StopCondition],
+        observer: &mut impl Observer,
+    ) -> Result<Option<&'c StopCondition>, DlaError> {
+        if conditions.is_empty() {
+            return Ok(None);
+        }
+
+        let start = Instant::now();
+        let mut gyration = Gyration::new(self.cells());
+
+        let mut max_dist2 = conditions
+            .iter()
+            .map(|c| match c {
+                StopCondition::Sphere { center, .. } => {
+                    self.cells().map(|c| c.dist2(*center)).max().unwrap_or(0)
+                }
+                _ => 0,
+            })
+            .collect::<Vec<_>>();
+
+        // whether the fractal dimension estimate started below the target of
+        // each condition, to detect when it crosses it
+        let mut below_target = vec![None; conditions.len()];
+
+        loop {
+            for ((c, max_dist2), below_target) in
+                conditions.iter().zip(&max_dist2).zip(&mut below_target)
+            {
+                let met = match c {
+                    StopCondition::Particles(n) => self.len() >= *n,
+                    StopCondition::MaxExtent(e) => {
+                        let d = self.bbox.dimensions();
+                        d.x.max(d.y).max(d.z) >= *e
+                    }
+                    StopCondition::Plane { axis, value } => {
+                        (self.bbox.lower()[*axis]..=self.bbox.upper()[*axis]).contains(value)
+                    }
+                    StopCondition::Sphere { radius, .. } => {
+                        i128::from(*max_dist2) >= i128::from(*radius).pow(2)
+                    }
+                    StopCondition::Timeout(t) => start.elapsed() >= *t,
+                    StopCondition::FractalDimension(d) => match gyration.fractal_dimension() {
+                        Some(fd) if gyration.n >= FRACTAL_DIMENSION_MIN_CELLS => {
+                            let below = fd < *d;
+                            below != *below_target.get_or_insert(below) || fd == *d
+                        }
+                        _ => false,
+                    },
+                };
+
+                if met {
+                    return Ok(Some(c));
+                }
+            }
+
+            let len = self.len();
+            let mut walker = self.spawn_walker(rng)?;
+            let cell = loop {
+                if let WalkerState::Stuck(cell) =
+                    self.step_walker_with(&mut walker, rng, observer)?
+                {
+                    break Some(cell);
+                }
+
+                if walker.stats().steps % TIMEOUT_CHECK_STEPS == 0 && timed_out(conditions, start) {
+                    break None;
+                }
+            };
+
+            // the walker might have stuck to an already existing cell
+            if let Some(cell) = cell.filter(|_| self.len() > len) {
+                gyration.add(cell);
+
+                for (c, max_dist2) in conditions.iter().zip(&mut max_dist2) {
+                    if let StopCondition::Sphere { center, .. } = c {
+                        *max_dist2 = (*max_dist2).max(cell.dist2(*center));
+                    }
+                }
+            }
+
+            if observer.interrupted() {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Estimate the fractal dimension of the DLA from the relation between the
+    /// number of cells and its radius of gyration. Return `None` when the DLA
+    /// is still too small to give a meaningful estimate.
+    pub fn fractal_dimension(&self) -> Option<f64> {
+        Gyration::new(self.cells()).fractal_dimension()
+    }
+
+    /// Root mean square distance of the cells from their center of mass.
+    pub fn radius_of_gyration(&self) -> f64 {
+        Gyration::new(self.cells()).radius()
+    }
+}
+
+fn timed_out(conditions: &[StopCondition], start: Instant) -> bool {
+    let elapsed = start.elapsed();
+
+    conditions
+        .iter()
+        .any(|c| matches!(c, StopCondition::Timeout(t) if elapsed >= *t))
+}
+
+/// Incremental computation of the radius of gyration of a set of points.
+#[derive(Debug, Clone)]
+struct Gyration {
+    n: usize,
+    sum: (f64, f64, f64),
+    sum2: f64,
+}
+
+impl Gyration {
+    fn new<'a>(pts: impl IntoIterator<Item = &'a Vec3>) -> Self {
+        let mut g = Gyration {
+            n: 0,
+            sum: (0.0, 0.0, 0.0),
+            sum2: 0.0,
+        };
+
+        for p in pts {
+            g.add(*p);
+        }
+
+        g
+    }
+
+    fn add(&mut self, p: Vec3) {
+        let (x, y, z) = (p.x as f64, p.y as f64, p.z as f64);
+
+        self.n += 1;
+        self.sum = (self.sum.0 + x, self.sum.1 + y, self.sum.2 + z);
+        self.sum2 += x * x + y * y + z * z;
+    }
+
+    fn radius(&self) -> f64 {
+        if self.n == 0 {
+            return 0.0;
+        }
+
+        let n = self.n as f64;
+        let (cx, cy, cz) = (self.sum.0 / n, self.sum.1 / n, self.sum.2 / n);
+        (self.sum2 / n - (cx * cx + cy * cy + cz * cz))
+            .max(0.0)
+            .sqrt()
+    }
+
+    fn fractal_dimension(&self) -> Option<f64> {
+        if self.n < 2 {
+            return None;
+        }
+
+        let rg = self.radius();
+        if rg <= 1.0 {
+            return None;
+        }
+
+        Some((self.n as f64).ln() / rg.ln())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn run_until_returns_the_first_met_condition() {
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+
+        assert_eq!(dla.run_until(&mut rng, &[]), Ok(None));
+
+        let conditions = [
+            StopCondition::Particles(10_000),
+            StopCondition::Plane {
+                axis: Axis::X,
+                value: 20,
+            },
+        ];
+        assert_eq!(
+            dla.run_until(&mut rng, &conditions),
+            Ok(Some(&conditions[1]))
+        );
+        assert!(dla.bbox().upper().x >= 20);
+        assert!(dla.len() < 10_000);
+
+        let conditions = [StopCondition::Particles(dla.len() + 10)];
+        assert_eq!(
+            dla.run_until(&mut rng, &conditions),
+            Ok(Some(&conditions[0]))
+        );
+    }
+
+    #[test]
+    fn run_until_stops_when_the_observer_is_interrupted() {
+        struct Limit(usize);
+
+        impl Observer for Limit {
+            fn notify(&mut self, event: &Event) {
+                if let Event::Attached { .. } = event {
+                    self.0 -= 1;
+                }
+            }
+
+            fn interrupted(&self) -> bool {
+                self.0 == 0
+            }
+        }
+
+        let mut rng = StdRng::seed_from_u64(42);
+        let mut dla = Dla::new(10, 8, vec![Vec3::new(0, 0, 0)]).unwrap();
+
+        let conditions = [StopCondition::Particles(100)];
+        assert_eq!(
+            dla.run_until_with(&mut rng, &conditions, &mut Limit(5)),
+            Ok(None)
+        );
+        assert_eq!(dla.len(), 6);
+    }
+}